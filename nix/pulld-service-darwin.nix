@@ -49,6 +49,9 @@ let
   mkService = name: serviceCfg:
     let
       logDir = "/var/log/pulld/${name}";
+      environment = {
+        PULLD_CONTROL_SOCKET = "/tmp/pulld-${name}.sock";
+      } // serviceCfg.environment;
     in
     {
       name = "pulld-${name}";
@@ -61,9 +64,10 @@ let
           KeepAlive = true;
           RunAtLoad = true;
           ExitTimeOut = 30 * 60;
-          EnvironmentVariables = serviceCfg.environment;
+          EnvironmentVariables = environment;
         };
-        inherit (serviceCfg) environment path;
+        inherit environment;
+        inherit (serviceCfg) path;
       };
   };
 in
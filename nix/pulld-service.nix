@@ -78,6 +78,7 @@ let
         User = serviceCfg.user;
         SupplementaryGroups = serviceCfg.extraGroups;
         EnvironmentFile = serviceCfg.environmentFile;
+        RuntimeDirectory = "pulld-${name}";
         ExecStart = lib.getExe serviceCfg.package;
         ExecReload = "${pkgs.coreutils}/bin/kill -TERM $MAINPID";
         Restart = "always";
@@ -86,7 +87,10 @@ let
       };
       restartIfChanged = false; # allows self updates
       reloadIfChanged = true;
-      inherit (serviceCfg) environment path;
+      environment = {
+        PULLD_CONTROL_SOCKET = "/run/pulld-${name}/pulld.sock";
+      } // serviceCfg.environment;
+      inherit (serviceCfg) path;
     };
   };
 in
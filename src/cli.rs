@@ -1,21 +1,95 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+use crate::workflow_config::parse_duration;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Backend {
     Github,
+    Gitlab,
+    Gitea,
+    Git,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Manage the daemon config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Show what the running daemon is doing
+    Status {
+        /// Print the status as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigCommand {
+    /// Validate the config and print the watched repositories
+    Check,
 }
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[arg(
+        long = "config",
+        value_name = "PATH",
+        env = "PULLD_CONFIG",
+        global = true,
+        help = "Path to the daemon config file, options given on the command line or in the environment take precedence"
+    )]
+    pub config: Option<PathBuf>,
+
+    #[arg(
+        long = "control_socket",
+        value_name = "PATH",
+        env = "PULLD_CONTROL_SOCKET",
+        default_value = "/run/pulld/pulld.sock",
+        global = true,
+        help = "Path of the Unix socket `pulld status` connects to"
+    )]
+    pub control_socket: PathBuf,
+
+    #[arg(
+        long = "log_format",
+        env = "PULLD_LOG_FORMAT",
+        default_value = "text",
+        help = "Format of the log output, json writes one object per event and output line",
+        value_enum
+    )]
+    pub log_format: LogFormat,
+
+    #[arg(
+        long = "metrics_listen",
+        value_name = "ADDRESS",
+        env = "PULLD_METRICS_LISTEN",
+        help = "Address to serve Prometheus metrics on at /metrics, e.g. 127.0.0.1:9184"
+    )]
+    pub metrics_listen: Option<String>,
+
     #[arg(
         long = "backend",
         env = "PULLD_BACKEND",
         help = "The backend to use",
         value_enum
     )]
-    pub backend: Backend,
+    pub backend: Option<Backend>,
 
     #[arg(
         long = "owner",
@@ -24,7 +98,7 @@ pub struct Cli {
         help = "The owner of the repository to watch for changes",
         value_enum
     )]
-    pub owner: String,
+    pub owner: Option<String>,
 
     #[arg(
         long = "repo",
@@ -33,7 +107,15 @@ pub struct Cli {
         help = "The repository to watch for changes",
         value_enum
     )]
-    pub repo: String,
+    pub repo: Option<String>,
+
+    #[arg(
+        long = "git_url",
+        value_name = "URL",
+        env = "PULLD_GIT_URL",
+        help = "URL of the repository to watch when using the git backend (ssh, https or file://)"
+    )]
+    pub git_url: Option<String>,
 
     #[arg(
         long = "branch",
@@ -55,9 +137,9 @@ pub struct Cli {
         long = "ssh_key_file",
         value_name = "PATH",
         env = "PULLD_SSH_KEY_FILE",
-        help = "Path to the SSH private key file used for git"
+        help = "Path to the SSH private key file used for git. Defaults to the SSH agent"
     )]
-    pub ssh_key_path: PathBuf,
+    pub ssh_key_path: Option<PathBuf>,
 
     #[arg(
         long = "poll_interval",
@@ -68,6 +150,60 @@ pub struct Cli {
     )]
     pub poll_interval: u64,
 
+    #[arg(
+        long = "max_parallel_jobs",
+        value_name = "COUNT",
+        env = "PULLD_MAX_PARALLEL_JOBS",
+        default_value_t = 1,
+        help = "Maximum number of jobs to run at the same time"
+    )]
+    pub max_parallel_jobs: usize,
+
+    #[arg(
+        long = "job_timeout",
+        value_name = "DURATION",
+        env = "PULLD_JOB_TIMEOUT",
+        value_parser = parse_duration,
+        help = "Timeout of jobs that don't set their own, e.g. 90s, 30m or 2h"
+    )]
+    pub job_timeout: Option<Duration>,
+
+    #[arg(
+        long = "termination_grace_period",
+        value_name = "SECONDS",
+        env = "PULLD_TERMINATION_GRACE_PERIOD",
+        default_value_t = 10,
+        help = "Seconds a timed out or canceled job gets to exit after SIGTERM before it is killed"
+    )]
+    pub termination_grace_period: u64,
+
+    #[arg(
+        long = "required_context",
+        value_name = "CONTEXT",
+        env = "PULLD_REQUIRED_CONTEXTS",
+        value_delimiter = ',',
+        help = "Status or check that must succeed on a commit before it is deployed, * for all commit statuses. Can be specified multiple times"
+    )]
+    pub required_contexts: Vec<String>,
+
+    #[arg(
+        long = "github_api_url",
+        value_name = "URL",
+        env = "PULLD_GITHUB_API_URL",
+        default_value = "https://api.github.com",
+        help = "Base URL of the GitHub API, e.g. https://github.example.com/api/v3 for GitHub Enterprise Server"
+    )]
+    pub github_api_url: String,
+
+    #[arg(
+        long = "github_ssh_host",
+        value_name = "HOST",
+        env = "PULLD_GITHUB_SSH_HOST",
+        default_value = "github.com",
+        help = "SSH host used to clone from GitHub"
+    )]
+    pub github_ssh_host: String,
+
     #[arg(
         long = "github_token",
         value_name = "TOKEN",
@@ -85,6 +221,104 @@ pub struct Cli {
     )]
     pub github_token_file: Option<PathBuf>,
 
+    #[arg(
+        long = "github_app_id",
+        value_name = "ID",
+        env = "PULLD_GITHUB_APP_ID",
+        help = "ID of the GitHub App to authenticate as instead of using a personal access token"
+    )]
+    pub github_app_id: Option<String>,
+
+    #[arg(
+        long = "github_app_private_key_file",
+        value_name = "PATH",
+        env = "PULLD_GITHUB_APP_PRIVATE_KEY_FILE",
+        help = "Path to the PEM encoded private key of the GitHub App"
+    )]
+    pub github_app_private_key_file: Option<PathBuf>,
+
+    #[arg(
+        long = "github_app_installation_id",
+        value_name = "ID",
+        env = "PULLD_GITHUB_APP_INSTALLATION_ID",
+        help = "Installation ID of the GitHub App. Looked up for the repository if not specified"
+    )]
+    pub github_app_installation_id: Option<u64>,
+
+    #[arg(
+        long = "github_check_runs",
+        env = "PULLD_GITHUB_CHECK_RUNS",
+        help = "Report jobs as check runs including their log output instead of commit statuses. Requires GitHub App authentication"
+    )]
+    pub github_check_runs: bool,
+
+    #[arg(
+        long = "gitlab_url",
+        value_name = "URL",
+        env = "PULLD_GITLAB_URL",
+        default_value = "https://gitlab.com",
+        help = "Base URL of the GitLab instance"
+    )]
+    pub gitlab_url: String,
+
+    #[arg(
+        long = "gitlab_ssh_host",
+        value_name = "HOST",
+        env = "PULLD_GITLAB_SSH_HOST",
+        help = "SSH host used to clone from GitLab. Defaults to the host of the GitLab URL"
+    )]
+    pub gitlab_ssh_host: Option<String>,
+
+    #[arg(
+        long = "gitlab_token",
+        value_name = "TOKEN",
+        env = "PULLD_GITLAB_TOKEN",
+        hide_env_values = true,
+        help = "GitLab access token for authentication"
+    )]
+    pub gitlab_token: Option<String>,
+
+    #[arg(
+        long = "gitlab_token_file",
+        value_name = "PATH",
+        env = "PULLD_GITLAB_TOKEN_FILE",
+        help = "Path to a file containing the GitLab access token for authentication"
+    )]
+    pub gitlab_token_file: Option<PathBuf>,
+
+    #[arg(
+        long = "gitea_url",
+        value_name = "URL",
+        env = "PULLD_GITEA_URL",
+        help = "Base URL of the Gitea or Forgejo instance"
+    )]
+    pub gitea_url: Option<String>,
+
+    #[arg(
+        long = "gitea_ssh_host",
+        value_name = "HOST",
+        env = "PULLD_GITEA_SSH_HOST",
+        help = "SSH host used to clone from Gitea. Defaults to the host of the Gitea URL"
+    )]
+    pub gitea_ssh_host: Option<String>,
+
+    #[arg(
+        long = "gitea_token",
+        value_name = "TOKEN",
+        env = "PULLD_GITEA_TOKEN",
+        hide_env_values = true,
+        help = "Gitea access token for authentication"
+    )]
+    pub gitea_token: Option<String>,
+
+    #[arg(
+        long = "gitea_token_file",
+        value_name = "PATH",
+        env = "PULLD_GITEA_TOKEN_FILE",
+        help = "Path to a file containing the Gitea access token for authentication"
+    )]
+    pub gitea_token_file: Option<PathBuf>,
+
     #[arg(
         long = "host_identifier",
         value_name = "NAME",
@@ -92,4 +326,37 @@ pub struct Cli {
         help = "Identifier of the local host. Defaults to the hostname"
     )]
     pub host_identifier: Option<String>,
+
+    #[arg(
+        long = "host_label",
+        value_name = "KEY=VALUE",
+        env = "PULLD_HOST_LABELS",
+        value_delimiter = ',',
+        help = "Label of the local host that jobs can select with `labels:`, can be repeated"
+    )]
+    pub host_labels: Vec<String>,
+
+    #[arg(
+        long = "host_labels_file",
+        value_name = "PATH",
+        env = "PULLD_HOST_LABELS_FILE",
+        help = "Path to a file containing labels of the local host, one KEY=VALUE per line"
+    )]
+    pub host_labels_file: Option<PathBuf>,
+
+    #[arg(
+        long = "secrets_dir",
+        value_name = "PATH",
+        env = "PULLD_SECRETS_DIR",
+        help = "Directory containing one file per secret that jobs can reference with `secrets:`"
+    )]
+    pub secrets_dir: Option<PathBuf>,
+
+    #[arg(
+        long = "secrets_file",
+        value_name = "PATH",
+        env = "PULLD_SECRETS_FILE",
+        help = "Path to an env file containing secrets that jobs can reference with `secrets:`"
+    )]
+    pub secrets_file: Option<PathBuf>,
 }
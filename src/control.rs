@@ -0,0 +1,192 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{BufRead, BufReader, ErrorKind, Write},
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Result, anyhow};
+use crossterm::style::Stylize;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    log::{self, Level, Scope},
+    runner::JobOutcome,
+    state::RunOutcome,
+};
+
+/// What pulld is doing with a watched repository, reported on the control socket.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RepoStatus {
+    pub url: String,
+    pub branch: String,
+    pub checkout_path: PathBuf,
+    /// Commit that is checked out.
+    pub current_commit: Option<String>,
+    /// Commit of the run in progress.
+    pub running_commit: Option<String>,
+    pub running_jobs: Vec<String>,
+    pub last_run_outcome: Option<RunOutcome>,
+    /// Last run of each job since pulld started.
+    pub jobs: BTreeMap<String, JobStatus>,
+    /// Unix timestamp of the next poll.
+    pub next_poll: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobStatus {
+    pub outcome: JobOutcome,
+    pub commit: String,
+    /// Unix timestamp of when the job finished.
+    pub finished_at: u64,
+    pub duration_secs: f64,
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+/// Answers status requests on a Unix socket at `path` in a background thread.
+pub fn serve(path: &Path, statuses: Vec<Arc<Mutex<RepoStatus>>>) -> Result<()> {
+    // a socket left behind by a previous process would make bind fail, but a socket that is
+    // still in use or a file that is no socket must not be removed
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(anyhow!("{} exists and is not a socket", path.display()));
+        }
+        match UnixStream::connect(path) {
+            Ok(_) => {
+                return Err(anyhow!(
+                    "Another process is listening on the control socket at {}",
+                    path.display()
+                ));
+            }
+            Err(err) if err.kind() == ErrorKind::ConnectionRefused => {
+                fs::remove_file(path)?;
+            }
+            Err(err) => {
+                return Err(anyhow!("Couldn't use control socket at {}: {}", path.display(), err));
+            }
+        }
+    }
+    let listener = UnixListener::bind(path)
+        .map_err(|err| anyhow!("Couldn't bind control socket at {}: {}", path.display(), err))?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let res = stream
+                .map_err(Into::into)
+                .and_then(|stream| handle_request(stream, &statuses));
+            if let Err(err) = res {
+                log::message(
+                    Level::Error,
+                    Scope::default(),
+                    format!("Failed to handle control request: {}", err).stylize(),
+                );
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn handle_request(mut stream: UnixStream, statuses: &[Arc<Mutex<RepoStatus>>]) -> Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    let mut command = String::new();
+    BufReader::new(&stream).read_line(&mut command)?;
+
+    match command.trim() {
+        "status" => {
+            let statuses = statuses
+                .iter()
+                .map(|status| status.lock().unwrap().clone())
+                .collect::<Vec<_>>();
+            serde_json::to_writer(&stream, &statuses)?;
+            stream.write_all(b"\n")?;
+        }
+        command => writeln!(stream, "error: unknown command {}", command)?,
+    }
+
+    Ok(())
+}
+
+/// Queries the status of the daemon listening on `path`.
+pub fn request_status(path: &Path) -> Result<Vec<RepoStatus>> {
+    let mut stream = UnixStream::connect(path).map_err(|err| {
+        anyhow!("Couldn't connect to pulld at {}: {}", path.display(), err)
+    })?;
+    stream.write_all(b"status\n")?;
+
+    let mut response = String::new();
+    BufReader::new(&stream).read_line(&mut response)?;
+    if let Some(err) = response.strip_prefix("error: ") {
+        return Err(anyhow!("pulld returned an error: {}", err.trim()));
+    }
+
+    Ok(serde_json::from_str(&response)?)
+}
+
+pub fn print_status(statuses: &[RepoStatus]) {
+    let now = unix_time();
+
+    for status in statuses {
+        println!(
+            "{}",
+            format!("{} ({})", status.url, status.branch).bold()
+        );
+        println!("  Checkout:  {}", status.checkout_path.display());
+        println!(
+            "  Commit:    {}",
+            status.current_commit.as_deref().unwrap_or("-")
+        );
+        match &status.running_commit {
+            Some(commit) => println!(
+                "  Run:       running {} (jobs: {})",
+                commit,
+                status.running_jobs.join(", ")
+            ),
+            None => println!(
+                "  Run:       {}",
+                status
+                    .last_run_outcome
+                    .map_or("-".to_owned(), |outcome| format!("last run {}", outcome_name(outcome)))
+            ),
+        }
+        if let Some(next_poll) = status.next_poll {
+            println!("  Next poll: in {}s", next_poll.saturating_sub(now));
+        }
+
+        if !status.jobs.is_empty() {
+            println!("  Jobs:");
+        }
+        for (job_name, job) in &status.jobs {
+            println!(
+                "    {:<20} {:<10} {:>8.1}s  {}  {}s ago",
+                job_name,
+                outcome_name(job.outcome),
+                job.duration_secs,
+                job.commit.get(..7).unwrap_or(&job.commit),
+                now.saturating_sub(job.finished_at)
+            );
+        }
+    }
+}
+
+/// Name of an outcome as it is serialized, e.g. `timed_out`.
+pub fn outcome_name(outcome: impl Serialize) -> String {
+    serde_json::to_value(outcome)
+        .ok()
+        .and_then(|value| value.as_str().map(ToOwned::to_owned))
+        .unwrap_or_default()
+}
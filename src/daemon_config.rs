@@ -0,0 +1,407 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use clap::{ArgMatches, parser::ValueSource};
+use serde::Deserialize;
+
+use crate::{
+    cli::{Backend, Cli, LogFormat},
+    workflow_config::parse_duration,
+};
+
+/// Configuration file of the pulld daemon, passed with `--config`. It accepts all options of
+/// the command line, options given on the command line or in the environment take precedence.
+#[derive(Debug, Default, Deserialize)]
+pub struct DaemonConfig {
+    pub control_socket: Option<PathBuf>,
+    pub log_format: Option<LogFormat>,
+    pub metrics_listen: Option<String>,
+    pub host_identifier: Option<String>,
+    pub host_labels: Option<HashMap<String, String>>,
+    pub host_labels_file: Option<PathBuf>,
+    /// Repositories to watch. Without entries, the repository configured at the top level is
+    /// watched.
+    #[serde(default)]
+    pub repos: Vec<RepoConfig>,
+    /// Settings of the watched repository, or defaults of all `repos`.
+    #[serde(flatten)]
+    pub defaults: RepoConfig,
+    /// Keys not consumed by the fields above, `deny_unknown_fields` doesn't work with `flatten`.
+    #[serde(flatten)]
+    unknown_fields: BTreeMap<String, serde_yaml_ng::Value>,
+}
+
+/// A watched repository. Settings that are not given are taken from the top level of the config
+/// and the command line.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RepoConfig {
+    pub backend: Option<Backend>,
+    pub owner: Option<String>,
+    pub repo: Option<String>,
+    pub git_url: Option<String>,
+    pub branch: Option<String>,
+    pub checkout_path: Option<PathBuf>,
+    pub ssh_key_file: Option<PathBuf>,
+    pub poll_interval: Option<u64>,
+    pub max_parallel_jobs: Option<usize>,
+    pub job_timeout: Option<String>,
+    pub termination_grace_period: Option<u64>,
+    pub required_contexts: Option<Vec<String>>,
+    pub github_api_url: Option<String>,
+    pub github_ssh_host: Option<String>,
+    pub github_token: Option<String>,
+    pub github_token_file: Option<PathBuf>,
+    pub github_app_id: Option<String>,
+    pub github_app_private_key_file: Option<PathBuf>,
+    pub github_app_installation_id: Option<u64>,
+    pub github_check_runs: Option<bool>,
+    pub gitlab_url: Option<String>,
+    pub gitlab_ssh_host: Option<String>,
+    pub gitlab_token: Option<String>,
+    pub gitlab_token_file: Option<PathBuf>,
+    pub gitea_url: Option<String>,
+    pub gitea_ssh_host: Option<String>,
+    pub gitea_token: Option<String>,
+    pub gitea_token_file: Option<PathBuf>,
+    pub secrets_dir: Option<PathBuf>,
+    pub secrets_file: Option<PathBuf>,
+}
+
+impl DaemonConfig {
+    pub fn load(path: &Path) -> Result<DaemonConfig> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| anyhow!("Couldn't read config at {}: {}", path.display(), err))?;
+        let config: DaemonConfig = serde_yaml_ng::from_str(&content)
+            .map_err(|err| anyhow!("Invalid config at {}: {}", path.display(), err))?;
+
+        if let Some(field) = config.unknown_fields.keys().next() {
+            return Err(anyhow!("Invalid config at {}: unknown field `{}`", path.display(), field));
+        }
+        Ok(config)
+    }
+
+    /// Applies the settings of the file to `cli`, unless they were given on the command line or
+    /// in the environment.
+    pub fn apply(&self, cli: &mut Cli, matches: &ArgMatches) -> Result<()> {
+        let explicit = |id: &str| explicit(matches, id);
+
+        if !explicit("control_socket") {
+            override_default(&mut cli.control_socket, &self.control_socket);
+        }
+        if !explicit("log_format") {
+            override_default(&mut cli.log_format, &self.log_format);
+        }
+        if !explicit("metrics_listen") {
+            override_value(&mut cli.metrics_listen, &self.metrics_listen);
+        }
+        if !explicit("host_identifier") {
+            override_value(&mut cli.host_identifier, &self.host_identifier);
+        }
+        if !explicit("host_labels")
+            && let Some(host_labels) = &self.host_labels
+        {
+            cli.host_labels = host_labels
+                .iter()
+                .map(|(key, value)| format!("{key}={value}"))
+                .collect();
+        }
+        if !explicit("host_labels_file") {
+            override_value(&mut cli.host_labels_file, &self.host_labels_file);
+        }
+
+        self.defaults.apply_to(cli, &explicit)
+    }
+
+    /// Settings of every watched repository. Options given on the command line or in the
+    /// environment also take precedence over the `repos` entries.
+    pub fn repos(&self, cli: &Cli, matches: &ArgMatches) -> Result<Vec<Cli>> {
+        if self.repos.is_empty() {
+            return Ok(vec![cli.clone()]);
+        }
+
+        self.repos
+            .iter()
+            .map(|repo| {
+                let mut cli = cli.clone();
+                repo.apply_to(&mut cli, &|id| explicit(matches, id))?;
+                Ok(cli)
+            })
+            .collect()
+    }
+}
+
+impl RepoConfig {
+    /// Applies the settings to `cli`, except for the arguments `keep` returns true for.
+    fn apply_to(&self, cli: &mut Cli, keep: &dyn Fn(&str) -> bool) -> Result<()> {
+        let apply = |id: &str| !keep(id);
+
+        if apply("backend") {
+            override_value(&mut cli.backend, &self.backend);
+        }
+        if apply("owner") {
+            override_value(&mut cli.owner, &self.owner);
+        }
+        if apply("repo") {
+            override_value(&mut cli.repo, &self.repo);
+        }
+        if apply("git_url") {
+            override_value(&mut cli.git_url, &self.git_url);
+        }
+        if apply("branch") {
+            override_default(&mut cli.branch, &self.branch);
+        }
+        if apply("checkout_path") {
+            override_value(&mut cli.checkout_path, &self.checkout_path);
+        }
+        if apply("ssh_key_path") {
+            override_value(&mut cli.ssh_key_path, &self.ssh_key_file);
+        }
+        if apply("poll_interval") {
+            override_default(&mut cli.poll_interval, &self.poll_interval);
+        }
+        if apply("max_parallel_jobs") {
+            override_default(&mut cli.max_parallel_jobs, &self.max_parallel_jobs);
+        }
+        if apply("job_timeout")
+            && let Some(job_timeout) = &self.job_timeout
+        {
+            cli.job_timeout = Some(parse_duration(job_timeout)?);
+        }
+        if apply("termination_grace_period") {
+            override_default(&mut cli.termination_grace_period, &self.termination_grace_period);
+        }
+        if apply("required_contexts") {
+            override_default(&mut cli.required_contexts, &self.required_contexts);
+        }
+
+        if apply("github_api_url") {
+            override_default(&mut cli.github_api_url, &self.github_api_url);
+        }
+        if apply("github_ssh_host") {
+            override_default(&mut cli.github_ssh_host, &self.github_ssh_host);
+        }
+        if apply("github_token") && apply("github_token_file") {
+            override_token(
+                (&mut cli.github_token, &mut cli.github_token_file),
+                (&self.github_token, &self.github_token_file),
+            );
+        }
+        if apply("github_app_id") {
+            override_value(&mut cli.github_app_id, &self.github_app_id);
+        }
+        if apply("github_app_private_key_file") {
+            override_value(&mut cli.github_app_private_key_file, &self.github_app_private_key_file);
+        }
+        if apply("github_app_installation_id") {
+            override_value(&mut cli.github_app_installation_id, &self.github_app_installation_id);
+        }
+        if apply("github_check_runs") {
+            override_default(&mut cli.github_check_runs, &self.github_check_runs);
+        }
+
+        if apply("gitlab_url") {
+            override_default(&mut cli.gitlab_url, &self.gitlab_url);
+        }
+        if apply("gitlab_ssh_host") {
+            override_value(&mut cli.gitlab_ssh_host, &self.gitlab_ssh_host);
+        }
+        if apply("gitlab_token") && apply("gitlab_token_file") {
+            override_token(
+                (&mut cli.gitlab_token, &mut cli.gitlab_token_file),
+                (&self.gitlab_token, &self.gitlab_token_file),
+            );
+        }
+
+        if apply("gitea_url") {
+            override_value(&mut cli.gitea_url, &self.gitea_url);
+        }
+        if apply("gitea_ssh_host") {
+            override_value(&mut cli.gitea_ssh_host, &self.gitea_ssh_host);
+        }
+        if apply("gitea_token") && apply("gitea_token_file") {
+            override_token(
+                (&mut cli.gitea_token, &mut cli.gitea_token_file),
+                (&self.gitea_token, &self.gitea_token_file),
+            );
+        }
+
+        if apply("secrets_dir") {
+            override_value(&mut cli.secrets_dir, &self.secrets_dir);
+        }
+        if apply("secrets_file") {
+            override_value(&mut cli.secrets_file, &self.secrets_file);
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether the argument `id` was given on the command line or in the environment.
+fn explicit(matches: &ArgMatches, id: &str) -> bool {
+    matches!(
+        matches.value_source(id),
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+    )
+}
+
+/// Overrides an optional argument if the config sets it.
+fn override_value<T: Clone>(value: &mut Option<T>, config_value: &Option<T>) {
+    if config_value.is_some() {
+        value.clone_from(config_value);
+    }
+}
+
+/// Overrides an argument with a default value if the config sets it.
+fn override_default<T: Clone>(value: &mut T, config_value: &Option<T>) {
+    if let Some(config_value) = config_value {
+        value.clone_from(config_value);
+    }
+}
+
+/// A token file takes precedence over a token, so a config that sets either one replaces both,
+/// otherwise a token file given elsewhere would win over the token of the config.
+fn override_token(
+    (token, token_file): (&mut Option<String>, &mut Option<PathBuf>),
+    (config_token, config_token_file): (&Option<String>, &Option<PathBuf>),
+) {
+    if config_token.is_some() || config_token_file.is_some() {
+        token.clone_from(config_token);
+        token_file.clone_from(config_token_file);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use clap::{CommandFactory, FromArgMatches};
+
+    use super::*;
+
+    fn parse(yaml: &str) -> DaemonConfig {
+        serde_yaml_ng::from_str(yaml).unwrap()
+    }
+
+    fn cli(args: &[&str]) -> (Cli, ArgMatches) {
+        let matches = Cli::command()
+            .try_get_matches_from(std::iter::once("pulld").chain(args.iter().copied()))
+            .unwrap();
+        (Cli::from_arg_matches(&matches).unwrap(), matches)
+    }
+
+    #[test]
+    fn repos_inherit_command_line_options() {
+        let config = parse(
+            "
+repos:
+  - repo: api
+    branch: release
+  - repo: web
+",
+        );
+        let (cli, matches) = cli(&["--backend", "github", "--owner", "acme"]);
+        let repos = config.repos(&cli, &matches).unwrap();
+
+        let names = repos
+            .iter()
+            .map(|repo| (repo.owner.as_deref(), repo.repo.as_deref(), repo.branch.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [(Some("acme"), Some("api"), "release"), (Some("acme"), Some("web"), "main")]
+        );
+    }
+
+    #[test]
+    fn repos_entries_reject_unknown_fields() {
+        let err = serde_yaml_ng::from_str::<DaemonConfig>("repos:\n  - repo: a\n    brnch: dev\n")
+            .unwrap_err();
+        assert!(err.to_string().contains("unknown field `brnch`"), "{err}");
+    }
+
+    #[test]
+    fn load_rejects_unknown_fields() {
+        let path = std::env::temp_dir().join(format!("pulld-config-test-{}.yaml", std::process::id()));
+        fs::write(&path, "backend: github\npoll_intervall: 5\n").unwrap();
+        let err = DaemonConfig::load(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+
+        assert!(err.to_string().ends_with("unknown field `poll_intervall`"), "{err}");
+    }
+
+    #[test]
+    fn config_overrides_defaults_but_not_explicit_arguments() {
+        let config = parse(
+            "
+backend: gitlab
+owner: group
+branch: production
+poll_interval: 30
+job_timeout: 10m
+gitlab_token_file: /etc/pulld/token
+host_labels:
+  role: web
+",
+        );
+        let (mut cli, matches) = cli(&["--branch", "dev", "--gitlab_token", "from-cli"]);
+        config.apply(&mut cli, &matches).unwrap();
+
+        assert_eq!(cli.backend, Some(Backend::Gitlab));
+        assert_eq!(cli.owner.as_deref(), Some("group"));
+        assert_eq!(cli.branch, "dev");
+        assert_eq!(cli.poll_interval, 30);
+        assert_eq!(cli.job_timeout, Some(Duration::from_secs(10 * 60)));
+        assert_eq!(cli.host_labels, ["role=web"]);
+        // a token given on the command line wins over a token file of the config
+        assert_eq!(cli.gitlab_token.as_deref(), Some("from-cli"));
+        assert_eq!(cli.gitlab_token_file, None);
+    }
+
+    #[test]
+    fn repos_inherit_defaults_and_keep_explicit_arguments() {
+        let config = parse(
+            "
+backend: github
+owner: acme
+poll_interval: 30
+repos:
+  - repo: api
+    branch: release
+  - repo: web
+    owner: other
+    poll_interval: 5
+",
+        );
+        let (mut cli, matches) = cli(&["--poll_interval", "60"]);
+        config.apply(&mut cli, &matches).unwrap();
+        let repos = config.repos(&cli, &matches).unwrap();
+
+        assert_eq!(repos.len(), 2);
+        assert_eq!(repos[0].owner.as_deref(), Some("acme"));
+        assert_eq!(repos[0].repo.as_deref(), Some("api"));
+        assert_eq!(repos[0].branch, "release");
+        assert_eq!(repos[1].owner.as_deref(), Some("other"));
+        assert_eq!(repos[1].branch, "main");
+        for repo in &repos {
+            assert_eq!(repo.backend, Some(Backend::Github));
+            assert_eq!(repo.poll_interval, 60);
+        }
+    }
+
+    #[test]
+    fn repos_default_to_the_top_level_repository() {
+        let config = parse("backend: gitea\nrepo: app\n");
+        let (mut cli, matches) = cli(&[]);
+        config.apply(&mut cli, &matches).unwrap();
+        let repos = config.repos(&cli, &matches).unwrap();
+
+        assert_eq!(repos.len(), 1);
+        assert_eq!(repos[0].backend, Some(Backend::Gitea));
+        assert_eq!(repos[0].repo.as_deref(), Some("app"));
+    }
+}
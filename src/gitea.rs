@@ -0,0 +1,199 @@
+use std::cmp::Reverse;
+
+use anyhow::Result;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::forge::{CreateStatus, Forge, Status, StatusState, host_from_url, next_page_url};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GiteaStatusState {
+    Pending,
+    Success,
+    Error,
+    Failure,
+    Warning,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GiteaStatus {
+    pub id: u64,
+    pub status: GiteaStatusState,
+    pub description: Option<String>,
+    pub target_url: Option<String>,
+    pub context: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GiteaCreateStatus {
+    pub state: GiteaStatusState,
+    pub target_url: Option<String>,
+    pub description: Option<String>,
+    pub context: Option<String>,
+}
+
+/// Gitea and Forgejo share the same commit status API.
+pub struct Gitea {
+    base_url: String,
+    ssh_host: String,
+    owner: String,
+    repo: String,
+    token: String,
+}
+
+impl Gitea {
+    pub fn new(
+        base_url: &str,
+        ssh_host: Option<&str>,
+        owner: &str,
+        repo: &str,
+        token: &str,
+    ) -> Result<Gitea> {
+        let base_url = base_url.trim_end_matches('/').to_owned();
+        let ssh_host = ssh_host
+            .map(ToOwned::to_owned)
+            .unwrap_or_else(|| host_from_url(&base_url).to_owned());
+
+        Ok(Gitea {
+            base_url,
+            ssh_host,
+            owner: owner.to_owned(),
+            repo: repo.to_owned(),
+            token: token.to_owned(),
+        })
+    }
+}
+
+impl Forge for Gitea {
+    fn git_ssh_url(&self) -> String {
+        format!("git@{}:{}/{}.git", self.ssh_host, self.owner, self.repo)
+    }
+
+    fn get_commit_statuses(&self, sha: &str) -> Result<Vec<Status>> {
+        let mut next_url = Some(format!(
+            "{}/api/v1/repos/{}/{}/statuses/{}?limit=50",
+            self.base_url, self.owner, self.repo, sha
+        ));
+        let mut statuses = Vec::new();
+
+        while let Some(url) = next_url {
+            let mut res = ureq::get(url)
+                .header("Accept", "application/json")
+                .header("User-Agent", "pulld")
+                .header("Authorization", format!("token {}", self.token))
+                .call()?;
+            next_url = next_page_url(&res);
+            statuses.extend(res.body_mut().read_json::<Vec<GiteaStatus>>()?);
+        }
+
+        // the endpoint also returns superseded statuses, keep only the latest of every context
+        // like the other forges do
+        Ok(statuses
+            .into_iter()
+            .sorted_by_key(|status| Reverse(status.id))
+            .unique_by(|status| status.context.clone())
+            .map(Into::into)
+            .collect())
+    }
+
+    fn set_commit_status(&self, sha: &str, status: CreateStatus) -> Result<()> {
+        let res = ureq::post(format!(
+            "{}/api/v1/repos/{}/{}/statuses/{}",
+            self.base_url, self.owner, self.repo, sha
+        ))
+        .header("Accept", "application/json")
+        .header("User-Agent", "pulld")
+        .header("Authorization", format!("token {}", self.token))
+        .send_json(&GiteaCreateStatus {
+            state: status.state.into(),
+            target_url: status.target_url,
+            description: status.description,
+            context: Some(status.context),
+        })?;
+
+        if !res.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Failed to set commit status. HTTP status: {}",
+                res.status()
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl From<GiteaStatus> for Status {
+    fn from(status: GiteaStatus) -> Self {
+        Status {
+            state: status.status.into(),
+            description: status.description,
+            target_url: status.target_url,
+            context: status.context,
+        }
+    }
+}
+
+impl From<StatusState> for GiteaStatusState {
+    fn from(status: StatusState) -> Self {
+        match status {
+            StatusState::Pending | StatusState::Running => Self::Pending,
+            StatusState::Success => Self::Success,
+            StatusState::Error => Self::Error,
+            StatusState::Failure => Self::Failure,
+        }
+    }
+}
+
+impl From<GiteaStatusState> for StatusState {
+    fn from(status: GiteaStatusState) -> Self {
+        match status {
+            GiteaStatusState::Pending => Self::Pending,
+            GiteaStatusState::Success | GiteaStatusState::Warning => Self::Success,
+            GiteaStatusState::Error => Self::Error,
+            GiteaStatusState::Failure => Self::Failure,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{MockServer, Response};
+
+    fn gitea_status(id: u64, context: &str, status: &str) -> String {
+        format!(
+            r#"{{"id":{id},"status":"{status}","description":null,"target_url":null,"context":"{context}","created_at":"2024-01-01T00:00:00Z","updated_at":"2024-01-01T00:00:00Z"}}"#
+        )
+    }
+
+    #[test]
+    fn get_commit_statuses_keeps_latest_status_per_context() {
+        let mut server = MockServer::new();
+        server.serve(vec![Response::json(&format!(
+            "[{},{},{},{}]",
+            gitea_status(4, "pulld/deploy", "success"),
+            gitea_status(3, "ci/test", "warning"),
+            gitea_status(2, "ci/test", "failure"),
+            gitea_status(1, "pulld/deploy", "pending"),
+        ))]);
+
+        let gitea = Gitea::new(server.url(), None, "owner", "repo", "secret").unwrap();
+        let statuses = gitea.get_commit_statuses("abc").unwrap();
+
+        let states = statuses
+            .iter()
+            .map(|status| (status.context.as_deref().unwrap(), status.state))
+            .collect_vec();
+        assert_eq!(
+            states,
+            [("pulld/deploy", StatusState::Success), ("ci/test", StatusState::Success)]
+        );
+
+        let requests = server.requests();
+        assert_eq!(requests[0].path, "/api/v1/repos/owner/repo/statuses/abc?limit=50");
+        assert_eq!(requests[0].header("Authorization"), Some("token secret"));
+    }
+}
@@ -1,11 +1,25 @@
-use anyhow::Result;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Result, anyhow};
+use base64::{
+    Engine,
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+};
+use ring::{
+    rand::SystemRandom,
+    signature::{RSA_PKCS1_SHA256, RsaKeyPair},
+};
 use serde::{Deserialize, Serialize};
 
-use crate::forge::{CreateStatus, Forge, Status, StatusState};
+use crate::forge::{CreateStatus, Forge, Status, StatusState, next_page_url};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GithubStatusResponse {
-    pub state: String,
+    pub state: GithubStatusState,
     pub statuses: Vec<GithubStatus>,
     pub sha: String,
     pub total_count: u32,
@@ -32,6 +46,42 @@ pub struct GithubStatus {
     pub updated_at: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GithubCheckRunsResponse {
+    pub total_count: u32,
+    pub check_runs: Vec<GithubCheckRun>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GithubCheckRun {
+    pub id: u64,
+    pub name: String,
+    pub status: String,
+    pub conclusion: Option<String>,
+    pub html_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GithubCheckRunOutput {
+    pub title: String,
+    pub summary: String,
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GithubUpdateCheckRun {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub head_sha: Option<String>,
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conclusion: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<GithubCheckRunOutput>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GithubCreateStatus {
     pub state: GithubStatusState,
@@ -40,54 +90,431 @@ pub struct GithubCreateStatus {
     pub context: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GithubInstallation {
+    pub id: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GithubAccessToken {
+    pub token: String,
+    pub expires_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct GithubAppClaims {
+    iat: u64,
+    exp: u64,
+    iss: String,
+}
+
+/// GitHub rejects check run output text longer than 65535 characters.
+const CHECK_RUN_TEXT_LIMIT: usize = 65535;
+
+/// Installation tokens are refreshed this long before they expire.
+const INSTALLATION_TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+enum GithubAuth {
+    Pat(String),
+    App(Box<GithubApp>),
+}
+
+struct GithubApp {
+    app_id: String,
+    key_pair: RsaKeyPair,
+    installation_id: Option<u64>,
+    token: Mutex<Option<(String, Instant)>>,
+}
+
+impl GithubApp {
+    /// Creates the short-lived JWT used to authenticate as the app itself.
+    fn jwt(&self) -> Result<String> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"RS256","typ":"JWT"}"#);
+        let claims = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&GithubAppClaims {
+            // allow for some clock drift, GitHub rejects tokens issued in the future
+            iat: now - 60,
+            exp: now + 9 * 60,
+            iss: self.app_id.clone(),
+        })?);
+
+        let message = format!("{header}.{claims}");
+        let mut signature = vec![0; self.key_pair.public().modulus_len()];
+        self.key_pair
+            .sign(
+                &RSA_PKCS1_SHA256,
+                &SystemRandom::new(),
+                message.as_bytes(),
+                &mut signature,
+            )
+            .map_err(|_| anyhow!("Failed to sign GitHub App JWT"))?;
+
+        Ok(format!("{message}.{}", URL_SAFE_NO_PAD.encode(signature)))
+    }
+}
+
 pub struct GitHub {
+    api_url: String,
+    ssh_host: String,
     owner: String,
     repo: String,
-    pat: String,
+    auth: GithubAuth,
+    report_check_runs: bool,
+    /// Check runs created for `(sha, context)` that are not completed yet.
+    check_runs: Mutex<HashMap<(String, String), u64>>,
 }
 
 impl GitHub {
-    pub fn new(owner: &str, repo: &str, pat: &str) -> Result<GitHub> {
+    pub fn new(api_url: &str, ssh_host: &str, owner: &str, repo: &str, pat: &str) -> Result<GitHub> {
         Ok(GitHub {
+            api_url: api_url.trim_end_matches('/').to_owned(),
+            ssh_host: ssh_host.to_owned(),
             owner: owner.to_owned(),
             repo: repo.to_owned(),
-            pat: pat.to_owned(),
+            auth: GithubAuth::Pat(pat.to_owned()),
+            report_check_runs: false,
+            check_runs: Mutex::new(HashMap::new()),
         })
     }
+
+    /// Authenticates as a GitHub App installation. If no `installation_id` is given, the
+    /// installation for the watched repository is looked up.
+    pub fn new_app(
+        api_url: &str,
+        ssh_host: &str,
+        owner: &str,
+        repo: &str,
+        app_id: &str,
+        private_key_pem: &str,
+        installation_id: Option<u64>,
+    ) -> Result<GitHub> {
+        Ok(GitHub {
+            api_url: api_url.trim_end_matches('/').to_owned(),
+            ssh_host: ssh_host.to_owned(),
+            owner: owner.to_owned(),
+            repo: repo.to_owned(),
+            auth: GithubAuth::App(Box::new(GithubApp {
+                app_id: app_id.to_owned(),
+                key_pair: parse_rsa_private_key(private_key_pem)?,
+                installation_id,
+                token: Mutex::new(None),
+            })),
+            report_check_runs: false,
+            check_runs: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Reports jobs as check runs including their log output instead of commit statuses.
+    /// Check runs can only be created when authenticated as a GitHub App.
+    pub fn with_check_runs(mut self, enabled: bool) -> Result<GitHub> {
+        if enabled && matches!(self.auth, GithubAuth::Pat(_)) {
+            return Err(anyhow!("Reporting check runs requires GitHub App authentication"));
+        }
+
+        self.report_check_runs = enabled;
+        Ok(self)
+    }
+
+    fn token(&self) -> Result<String> {
+        match &self.auth {
+            GithubAuth::Pat(pat) => Ok(pat.clone()),
+            GithubAuth::App(app) => {
+                let mut token = app.token.lock().unwrap();
+                if let Some((token, refresh_at)) = token.as_ref()
+                    && Instant::now() < *refresh_at
+                {
+                    return Ok(token.clone());
+                }
+
+                let new_token = self.request_installation_token(app)?;
+                let valid_for = parse_timestamp(&new_token.expires_at)?
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default()
+                    .saturating_sub(INSTALLATION_TOKEN_REFRESH_MARGIN);
+                *token = Some((new_token.token.clone(), Instant::now() + valid_for));
+                Ok(new_token.token)
+            }
+        }
+    }
+
+    fn request_installation_token(&self, app: &GithubApp) -> Result<GithubAccessToken> {
+        let jwt = app.jwt()?;
+
+        let installation_id = match app.installation_id {
+            Some(id) => id,
+            None => {
+                ureq::get(format!(
+                    "{}/repos/{}/{}/installation",
+                    self.api_url, self.owner, self.repo
+                ))
+                .header("Accept", "application/vnd.github+json")
+                .header("X-GitHub-Api-Version", "2022-11-28")
+                .header("User-Agent", "pulld")
+                .header("Authorization", format!("Bearer {}", jwt))
+                .call()?
+                .body_mut()
+                .read_json::<GithubInstallation>()?
+                .id
+            }
+        };
+
+        let res = ureq::post(format!(
+            "{}/app/installations/{}/access_tokens",
+            self.api_url, installation_id
+        ))
+        .header("Accept", "application/vnd.github+json")
+        .header("X-GitHub-Api-Version", "2022-11-28")
+        .header("User-Agent", "pulld")
+        .header("Authorization", format!("Bearer {}", jwt))
+        .send_empty()?
+        .body_mut()
+        .read_json::<GithubAccessToken>()?;
+
+        Ok(res)
+    }
 }
 
-impl Forge for GitHub {
-    fn git_ssh_url(&self) -> String {
-        format!("git@github.com:{}/{}.git", self.owner, self.repo)
+impl GitHub {
+    /// Fetches the combined status of a commit, following the `Link` headers to collect the
+    /// statuses from all pages.
+    fn get_combined_status(&self, sha: &str) -> Result<GithubStatusResponse> {
+        let mut next_url = Some(format!(
+            "{}/repos/{}/{}/commits/{}/status?per_page=100",
+            self.api_url, self.owner, self.repo, sha
+        ));
+        let mut combined: Option<GithubStatusResponse> = None;
+
+        while let Some(url) = next_url {
+            let mut res = ureq::get(url)
+                .header("Accept", "application/vnd.github+json")
+                .header("X-GitHub-Api-Version", "2022-11-28")
+                .header("User-Agent", "pulld")
+                .header("Authorization", format!("Bearer {}", self.token()?))
+                .call()?;
+            next_url = next_page_url(&res);
+            let page = res.body_mut().read_json::<GithubStatusResponse>()?;
+
+            match combined.as_mut() {
+                Some(combined) => combined.statuses.extend(page.statuses),
+                None => combined = Some(page),
+            }
+        }
+
+        combined.ok_or_else(|| anyhow!("No commit status returned"))
     }
+}
 
-    fn get_commit_statuses(&self, sha: &str) -> Result<Vec<Status>> {
+impl GitHub {
+    /// Creates a check run for the context of `status` or updates the one created before, also
+    /// by a previous pulld process.
+    fn set_check_run(&self, sha: &str, status: CreateStatus) -> Result<()> {
+        let key = (sha.to_owned(), status.context.clone());
+        let existing_id = self.check_runs.lock().unwrap().get(&key).copied();
+        let existing_id = match existing_id {
+            Some(id) => Some(id),
+            None => self.find_incomplete_check_run(sha, &status.context)?,
+        };
+
+        let (check_status, conclusion) = match (status.state, existing_id) {
+            (StatusState::Pending, None) => ("queued", None),
+            (StatusState::Pending, Some(_)) | (StatusState::Running, _) => ("in_progress", None),
+            (StatusState::Success, _) => ("completed", Some("success")),
+            (StatusState::Failure, _) => ("completed", Some("failure")),
+            (StatusState::Error, _) => ("completed", Some("cancelled")),
+        };
+
+        let description = status.description.unwrap_or_default();
+        let body = GithubUpdateCheckRun {
+            name: status.context,
+            head_sha: existing_id.is_none().then(|| sha.to_owned()),
+            status: check_status.to_owned(),
+            conclusion: conclusion.map(ToOwned::to_owned),
+            details_url: status.target_url,
+            output: Some(GithubCheckRunOutput {
+                title: description.clone(),
+                summary: description,
+                text: status.output.map(|output| check_run_text(&output)),
+            }),
+        };
+
+        let check_run = match existing_id {
+            None => ureq::post(format!(
+                "{}/repos/{}/{}/check-runs",
+                self.api_url, self.owner, self.repo
+            ))
+            .header("Accept", "application/vnd.github+json")
+            .header("X-GitHub-Api-Version", "2022-11-28")
+            .header("User-Agent", "pulld")
+            .header("Authorization", format!("Bearer {}", self.token()?))
+            .send_json(&body)?
+            .body_mut()
+            .read_json::<GithubCheckRun>()?,
+            Some(id) => ureq::patch(format!(
+                "{}/repos/{}/{}/check-runs/{}",
+                self.api_url, self.owner, self.repo, id
+            ))
+            .header("Accept", "application/vnd.github+json")
+            .header("X-GitHub-Api-Version", "2022-11-28")
+            .header("User-Agent", "pulld")
+            .header("Authorization", format!("Bearer {}", self.token()?))
+            .send_json(&body)?
+            .body_mut()
+            .read_json::<GithubCheckRun>()?,
+        };
+
+        let mut check_runs = self.check_runs.lock().unwrap();
+        if conclusion.is_some() {
+            check_runs.remove(&key);
+        } else {
+            check_runs.insert(key, check_run.id);
+        }
+
+        Ok(())
+    }
+}
+
+impl GitHub {
+    /// Looks up a check run named `name` on `sha` that is not completed yet, e.g. one left
+    /// behind by a restart.
+    fn find_incomplete_check_run(&self, sha: &str, name: &str) -> Result<Option<u64>> {
         let res = ureq::get(format!(
-            "https://api.github.com/repos/{}/{}/commits/{}/status",
-            self.owner, self.repo, sha
+            "{}/repos/{}/{}/commits/{}/check-runs",
+            self.api_url, self.owner, self.repo, sha
         ))
+        .query("check_name", name)
+        .query("filter", "latest")
         .header("Accept", "application/vnd.github+json")
         .header("X-GitHub-Api-Version", "2022-11-28")
         .header("User-Agent", "pulld")
-        .header("Authorization", format!("Bearer {}", self.pat))
-        .query("per_page", 100.to_string())
+        .header("Authorization", format!("Bearer {}", self.token()?))
         .call()?
         .body_mut()
-        .read_json::<GithubStatusResponse>()?;
+        .read_json::<GithubCheckRunsResponse>()?;
+
+        Ok(res
+            .check_runs
+            .into_iter()
+            .find(|check_run| check_run.name == name && check_run.status != "completed")
+            .map(|check_run| check_run.id))
+    }
+}
+
+/// Formats job output as a markdown code block, keeping only the end of the log if it exceeds
+/// the size GitHub accepts.
+fn check_run_text(output: &str) -> String {
+    const FENCE: &str = "```\n";
+    const TRUNCATED: &str = "(output truncated)\n";
+    let available = CHECK_RUN_TEXT_LIMIT - 2 * FENCE.len() - TRUNCATED.len() - 1;
+
+    if output.len() <= available {
+        return format!("{FENCE}{output}\n{FENCE}");
+    }
+
+    let mut start = output.len() - available;
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+    format!("{TRUNCATED}{FENCE}{}\n{FENCE}", &output[start..])
+}
+
+/// Parses a UTC timestamp like `2016-07-11T22:14:10Z` as returned by the GitHub API.
+fn parse_timestamp(timestamp: &str) -> Result<SystemTime> {
+    let invalid = || anyhow!("Invalid timestamp {}", timestamp);
+    let (date, time) = timestamp
+        .strip_suffix('Z')
+        .and_then(|timestamp| timestamp.split_once('T'))
+        .ok_or_else(invalid)?;
+    let parse = |value: &str, separator: char| -> Result<Vec<i64>> {
+        value
+            .split(separator)
+            .map(|part| part.parse().map_err(|_err| invalid()))
+            .collect()
+    };
+    let (date, time) = (parse(date, '-')?, parse(time, ':')?);
+    let (&[year, month, day], &[hour, minute, second]) = (&date[..], &time[..]) else {
+        return Err(invalid());
+    };
+
+    // days since the epoch in the proleptic Gregorian calendar, with years starting in March
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
 
-        // TODO: collect all pages
+    let secs = days * 86400 + hour * 3600 + minute * 60 + second;
+    let secs = u64::try_from(secs).map_err(|_err| invalid())?;
+    Ok(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Parses a PEM encoded RSA private key as downloaded from the GitHub App settings (PKCS#1)
+/// or converted to PKCS#8.
+fn parse_rsa_private_key(pem: &str) -> Result<RsaKeyPair> {
+    let der = STANDARD.decode(
+        pem.lines()
+            .filter(|line| !line.starts_with("-----"))
+            .collect::<String>(),
+    )?;
+
+    let key_pair = if pem.contains("BEGIN RSA PRIVATE KEY") {
+        RsaKeyPair::from_der(&der)
+    } else {
+        RsaKeyPair::from_pkcs8(&der)
+    };
+
+    key_pair.map_err(|err| anyhow!("Invalid GitHub App private key: {}", err))
+}
+
+impl Forge for GitHub {
+    fn git_ssh_url(&self) -> String {
+        format!("git@{}:{}/{}.git", self.ssh_host, self.owner, self.repo)
+    }
+
+    fn get_commit_statuses(&self, sha: &str) -> Result<Vec<Status>> {
+        let res = self.get_combined_status(sha)?;
         Ok(res.statuses.into_iter().map(Into::into).collect())
     }
 
+    fn get_combined_state(&self, sha: &str) -> Result<StatusState> {
+        let res = self.get_combined_status(sha)?;
+        Ok(res.state.into())
+    }
+
+    fn get_check_runs(&self, sha: &str) -> Result<Vec<Status>> {
+        let mut next_url = Some(format!(
+            "{}/repos/{}/{}/commits/{}/check-runs?per_page=100",
+            self.api_url, self.owner, self.repo, sha
+        ));
+        let mut check_runs = Vec::new();
+
+        while let Some(url) = next_url {
+            let mut res = ureq::get(url)
+                .header("Accept", "application/vnd.github+json")
+                .header("X-GitHub-Api-Version", "2022-11-28")
+                .header("User-Agent", "pulld")
+                .header("Authorization", format!("Bearer {}", self.token()?))
+                .call()?;
+            next_url = next_page_url(&res);
+            check_runs.extend(res.body_mut().read_json::<GithubCheckRunsResponse>()?.check_runs);
+        }
+
+        Ok(check_runs.into_iter().map(Into::into).collect())
+    }
+
     fn set_commit_status(&self, sha: &str, status: CreateStatus) -> Result<()> {
+        if self.report_check_runs {
+            return self.set_check_run(sha, status);
+        }
+
         let res = ureq::post(format!(
-            "https://api.github.com/repos/{}/{}/statuses/{}",
-            self.owner, self.repo, sha
+            "{}/repos/{}/{}/statuses/{}",
+            self.api_url, self.owner, self.repo, sha
         ))
         .header("Accept", "application/vnd.github+json")
         .header("X-GitHub-Api-Version", "2022-11-28")
         .header("User-Agent", "pulld")
-        .header("Authorization", format!("Bearer {}", self.pat))
+        .header("Authorization", format!("Bearer {}", self.token()?))
         .send_json(&GithubCreateStatus {
             state: status.state.into(),
             target_url: status.target_url,
@@ -106,13 +533,32 @@ impl Forge for GitHub {
     }
 }
 
-impl Into<Status> for GithubStatus {
-    fn into(self) -> Status {
+impl From<GithubStatus> for Status {
+    fn from(status: GithubStatus) -> Self {
+        Status {
+            state: status.state.into(),
+            description: status.description,
+            target_url: status.target_url,
+            context: status.context,
+        }
+    }
+}
+
+impl From<GithubCheckRun> for Status {
+    fn from(check_run: GithubCheckRun) -> Self {
+        let state = match (check_run.status.as_str(), check_run.conclusion.as_deref()) {
+            ("completed", Some("success" | "neutral" | "skipped")) => StatusState::Success,
+            ("completed", Some("cancelled")) => StatusState::Error,
+            ("completed", _) => StatusState::Failure,
+            ("in_progress", _) => StatusState::Running,
+            _ => StatusState::Pending,
+        };
+
         Status {
-            state: self.state.into(),
-            description: self.description,
-            target_url: self.target_url,
-            context: self.context,
+            state,
+            description: check_run.conclusion,
+            target_url: check_run.html_url,
+            context: Some(check_run.name),
         }
     }
 }
@@ -120,7 +566,7 @@ impl Into<Status> for GithubStatus {
 impl From<StatusState> for GithubStatusState {
     fn from(status: StatusState) -> Self {
         match status {
-            StatusState::Pending => Self::Pending,
+            StatusState::Pending | StatusState::Running => Self::Pending,
             StatusState::Success => Self::Success,
             StatusState::Error => Self::Error,
             StatusState::Failure => Self::Failure,
@@ -128,14 +574,154 @@ impl From<StatusState> for GithubStatusState {
     }
 }
 
-impl Into<StatusState> for GithubStatusState {
-    fn into(self) -> StatusState {
-        match self {
-            Self::Pending => StatusState::Pending,
-            Self::Success => StatusState::Success,
-            Self::Error => StatusState::Error,
-            Self::Failure => StatusState::Failure,
-            _ => StatusState::Pending, // TODO
+impl From<GithubStatusState> for StatusState {
+    fn from(status: GithubStatusState) -> Self {
+        match status {
+            GithubStatusState::Pending => Self::Pending,
+            GithubStatusState::Success => Self::Success,
+            GithubStatusState::Error => Self::Error,
+            GithubStatusState::Failure => Self::Failure,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ring::signature::{RSA_PKCS1_2048_8192_SHA256, UnparsedPublicKey};
+
+    use super::*;
+    use crate::mock_server::{MockServer, Response};
+
+    const APP_PRIVATE_KEY: &str = include_str!("../testdata/github_app_key.pem");
+
+    const STATUS: &str = r#"{"state":"success","statuses":[],"sha":"abc","total_count":0}"#;
+
+    #[test]
+    fn check_run_text_wraps_output_in_code_block() {
+        assert_eq!(check_run_text("line 1\nline 2"), "```\nline 1\nline 2\n```\n");
+    }
+
+    #[test]
+    fn check_run_text_keeps_end_of_long_output() {
+        let output = format!("{}end", "ä".repeat(CHECK_RUN_TEXT_LIMIT));
+        let text = check_run_text(&output);
+
+        assert!(text.len() <= CHECK_RUN_TEXT_LIMIT);
+        assert!(text.starts_with("(output truncated)\n```\n"));
+        assert!(text.ends_with("end\n```\n"));
+    }
+
+    #[test]
+    fn get_commit_statuses_follows_pagination() {
+        let mut server = MockServer::new();
+        let next_page = format!(r#"<{}/page-2>; rel="next""#, server.url());
+        let status = |context: &str, state: &str| {
+            format!(
+                r#"{{"state":"pending","statuses":[{{"id":1,"node_id":"node","state":"{state}","description":null,"target_url":null,"context":"{context}","created_at":"2024-01-01T00:00:00Z","updated_at":"2024-01-01T00:00:00Z"}}],"sha":"abc","total_count":2}}"#
+            )
+        };
+        server.serve(vec![
+            Response::json(&status("ci/build", "success")).header("Link", &next_page),
+            Response::json(&status("ci/test", "pending")),
+        ]);
+
+        let github = GitHub::new(server.url(), "github.com", "owner", "repo", "secret").unwrap();
+        let statuses = github.get_commit_statuses("abc").unwrap();
+
+        let contexts = statuses.iter().map(|status| status.context.as_deref()).collect::<Vec<_>>();
+        assert_eq!(contexts, [Some("ci/build"), Some("ci/test")]);
+        assert_eq!(server.requests()[1].path, "/page-2");
+    }
+
+    #[test]
+    fn api_url_and_ssh_host_are_configurable() {
+        let mut server = MockServer::new();
+        server.serve(vec![Response::json(STATUS)]);
+
+        let api_url = format!("{}/api/v3/", server.url());
+        let github = GitHub::new(&api_url, "github.example.com", "owner", "repo", "secret").unwrap();
+        github.get_commit_statuses("abc").unwrap();
+        assert_eq!(github.git_ssh_url(), "git@github.example.com:owner/repo.git");
+
+        let requests = server.requests();
+        assert_eq!(requests[0].path, "/api/v3/repos/owner/repo/commits/abc/status?per_page=100");
+        assert_eq!(requests[0].header("Authorization"), Some("Bearer secret"));
+    }
+
+    #[test]
+    fn parse_timestamp_returns_unix_time() {
+        let parse = |timestamp| {
+            parse_timestamp(timestamp)
+                .unwrap()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        };
+        assert_eq!(parse("1970-01-01T00:00:00Z"), 0);
+        assert_eq!(parse("2016-07-11T22:14:10Z"), 1468275250);
+        assert_eq!(parse("2024-02-29T12:00:00Z"), 1709208000);
+        assert!(parse_timestamp("2024-02-29 12:00:00").is_err());
+    }
+
+    #[test]
+    fn app_exchanges_jwt_for_installation_token_and_refreshes_it() {
+        let mut server = MockServer::new();
+        server.serve(vec![
+            Response::json(r#"{"id":42}"#),
+            Response::json(r#"{"token":"expired-token","expires_at":"2020-01-01T00:00:00Z"}"#),
+            Response::json(STATUS),
+            Response::json(r#"{"id":42}"#),
+            Response::json(r#"{"token":"new-token","expires_at":"2999-01-01T00:00:00Z"}"#),
+            Response::json(STATUS),
+            Response::json(STATUS),
+        ]);
+
+        let github = GitHub::new_app(
+            server.url(),
+            "github.com",
+            "owner",
+            "repo",
+            "1234",
+            APP_PRIVATE_KEY,
+            None,
+        )
+        .unwrap();
+        for _ in 0..3 {
+            github.get_commit_statuses("abc").unwrap();
+        }
+
+        let requests = server.requests();
+        let paths = requests
+            .iter()
+            .map(|request| format!("{} {}", request.method, request.path))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                "GET /repos/owner/repo/installation",
+                "POST /app/installations/42/access_tokens",
+                "GET /repos/owner/repo/commits/abc/status?per_page=100",
+                "GET /repos/owner/repo/installation",
+                "POST /app/installations/42/access_tokens",
+                "GET /repos/owner/repo/commits/abc/status?per_page=100",
+                "GET /repos/owner/repo/commits/abc/status?per_page=100",
+            ]
+        );
+        let authorization = |i: usize| requests[i].header("Authorization").unwrap();
+        assert_eq!(authorization(2), "Bearer expired-token");
+        assert_eq!(authorization(5), "Bearer new-token");
+        assert_eq!(authorization(6), "Bearer new-token");
+
+        // the token requests are authenticated with a JWT signed by the app's private key
+        let jwt = authorization(1).strip_prefix("Bearer ").unwrap();
+        let (message, signature) = jwt.rsplit_once('.').unwrap();
+        let (_, claims) = message.split_once('.').unwrap();
+        let claims: serde_json::Value =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(claims).unwrap()).unwrap();
+        assert_eq!(claims["iss"], "1234");
+        let key_pair = parse_rsa_private_key(APP_PRIVATE_KEY).unwrap();
+        UnparsedPublicKey::new(&RSA_PKCS1_2048_8192_SHA256, key_pair.public().as_ref())
+            .verify(message.as_bytes(), &URL_SAFE_NO_PAD.decode(signature).unwrap())
+            .unwrap();
+    }
+}
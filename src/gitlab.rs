@@ -0,0 +1,225 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::forge::{CreateStatus, Forge, Status, StatusState, host_from_url, next_page_url};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GitlabStatusState {
+    Created,
+    WaitingForResource,
+    Preparing,
+    Pending,
+    Running,
+    Success,
+    Failed,
+    Canceled,
+    Skipped,
+    Manual,
+    Scheduled,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GitlabStatus {
+    pub id: u64,
+    pub sha: String,
+    pub status: GitlabStatusState,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub target_url: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GitlabCreateStatus {
+    pub state: GitlabStatusState,
+    pub name: String,
+    pub target_url: Option<String>,
+    pub description: Option<String>,
+}
+
+pub struct GitLab {
+    base_url: String,
+    ssh_host: String,
+    owner: String,
+    repo: String,
+    token: String,
+}
+
+impl GitLab {
+    pub fn new(
+        base_url: &str,
+        ssh_host: Option<&str>,
+        owner: &str,
+        repo: &str,
+        token: &str,
+    ) -> Result<GitLab> {
+        let base_url = base_url.trim_end_matches('/').to_owned();
+        let ssh_host = ssh_host
+            .map(ToOwned::to_owned)
+            .unwrap_or_else(|| host_from_url(&base_url).to_owned());
+
+        Ok(GitLab {
+            base_url,
+            ssh_host,
+            owner: owner.to_owned(),
+            repo: repo.to_owned(),
+            token: token.to_owned(),
+        })
+    }
+
+    /// URL-encoded `namespace/project` path, which GitLab accepts in place of a numeric project id.
+    fn project_id(&self) -> String {
+        format!("{}/{}", self.owner, self.repo).replace('/', "%2F")
+    }
+}
+
+impl Forge for GitLab {
+    fn git_ssh_url(&self) -> String {
+        format!("git@{}:{}/{}.git", self.ssh_host, self.owner, self.repo)
+    }
+
+    fn get_commit_statuses(&self, sha: &str) -> Result<Vec<Status>> {
+        let mut next_url = Some(format!(
+            "{}/api/v4/projects/{}/repository/commits/{}/statuses?per_page=100",
+            self.base_url,
+            self.project_id(),
+            sha
+        ));
+        let mut statuses = Vec::new();
+
+        while let Some(url) = next_url {
+            let mut res = ureq::get(url)
+                .header("User-Agent", "pulld")
+                .header("PRIVATE-TOKEN", &self.token)
+                .call()?;
+            next_url = next_page_url(&res);
+            statuses.extend(res.body_mut().read_json::<Vec<GitlabStatus>>()?);
+        }
+
+        Ok(statuses.into_iter().map(Into::into).collect())
+    }
+
+    fn set_commit_status(&self, sha: &str, status: CreateStatus) -> Result<()> {
+        let res = ureq::post(format!(
+            "{}/api/v4/projects/{}/statuses/{}",
+            self.base_url,
+            self.project_id(),
+            sha
+        ))
+        .header("User-Agent", "pulld")
+        .header("PRIVATE-TOKEN", &self.token)
+        .send_json(&GitlabCreateStatus {
+            state: status.state.into(),
+            name: status.context,
+            target_url: status.target_url,
+            description: status.description,
+        })?;
+
+        if !res.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Failed to set commit status. HTTP status: {}",
+                res.status()
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl From<GitlabStatus> for Status {
+    fn from(status: GitlabStatus) -> Self {
+        Status {
+            state: status.status.into(),
+            description: status.description,
+            target_url: status.target_url,
+            context: status.name,
+        }
+    }
+}
+
+impl From<StatusState> for GitlabStatusState {
+    fn from(status: StatusState) -> Self {
+        match status {
+            StatusState::Pending => Self::Pending,
+            // GitLab rejects a transition from a state to the same state, so pending and
+            // running are told apart and every retry starts from a failed status
+            StatusState::Running => Self::Running,
+            StatusState::Success => Self::Success,
+            StatusState::Error => Self::Failed,
+            StatusState::Failure => Self::Failed,
+        }
+    }
+}
+
+impl From<GitlabStatusState> for StatusState {
+    fn from(status: GitlabStatusState) -> Self {
+        match status {
+            GitlabStatusState::Success | GitlabStatusState::Skipped => Self::Success,
+            GitlabStatusState::Failed => Self::Failure,
+            GitlabStatusState::Canceled => Self::Error,
+            GitlabStatusState::Running => Self::Running,
+            GitlabStatusState::Created
+            | GitlabStatusState::WaitingForResource
+            | GitlabStatusState::Preparing
+            | GitlabStatusState::Pending
+            | GitlabStatusState::Manual
+            | GitlabStatusState::Scheduled => Self::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{MockServer, Response};
+
+    #[test]
+    fn get_commit_statuses_uses_project_path_and_token() {
+        let mut server = MockServer::new();
+        server.serve(vec![Response::json(
+            r#"[{"id":1,"sha":"abc","status":"canceled","name":"test","description":null,"target_url":null,"created_at":"2024-01-01T00:00:00Z"}]"#,
+        )]);
+
+        let gitlab = GitLab::new(server.url(), None, "group", "repo", "secret").unwrap();
+        let statuses = gitlab.get_commit_statuses("abc").unwrap();
+        assert_eq!(statuses[0].state, StatusState::Error);
+        assert_eq!(statuses[0].context.as_deref(), Some("test"));
+
+        let requests = server.requests();
+        assert_eq!(
+            requests[0].path,
+            "/api/v4/projects/group%2Frepo/repository/commits/abc/statuses?per_page=100"
+        );
+        assert_eq!(requests[0].header("PRIVATE-TOKEN"), Some("secret"));
+    }
+
+    #[test]
+    fn set_commit_status_sends_name_and_running_state() {
+        let mut server = MockServer::new();
+        server.serve(vec![Response::json("{}")]);
+
+        let gitlab = GitLab::new(server.url(), None, "group", "repo", "secret").unwrap();
+        let status = CreateStatus {
+            state: StatusState::Running,
+            description: None,
+            target_url: None,
+            context: "pulld/deploy".to_owned(),
+            output: None,
+        };
+        gitlab.set_commit_status("abc", status).unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].path, "/api/v4/projects/group%2Frepo/statuses/abc");
+        let body = requests[0].json();
+        assert_eq!(body["state"], "running");
+        assert_eq!(body["name"], "pulld/deploy");
+    }
+
+    #[test]
+    fn ssh_host_defaults_to_host_of_base_url() {
+        let gitlab = GitLab::new("https://gitlab.example.com:8443/", None, "group", "repo", "secret").unwrap();
+        assert_eq!(gitlab.git_ssh_url(), "git@gitlab.example.com:group/repo.git");
+    }
+}
@@ -0,0 +1,165 @@
+use std::{
+    fmt::Display,
+    sync::OnceLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crossterm::style::StyledContent;
+use serde::Serialize;
+
+use crate::{cli::LogFormat, runner::JobOutcome, state::RunOutcome};
+
+static LOG_FORMAT: OnceLock<LogFormat> = OnceLock::new();
+
+/// Sets the format of everything the daemon logs, can only be set once.
+pub fn init(format: LogFormat) {
+    let _ = LOG_FORMAT.set(format);
+}
+
+fn format() -> LogFormat {
+    LOG_FORMAT.get().copied().unwrap_or(LogFormat::Text)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Repository and job a message is about.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct Scope<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repo: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job: Option<&'a str>,
+}
+
+/// What the JSON log format reports, one object per line with the name of the event in `event`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// The remote branch was fetched.
+    Poll { repo: &'a str, commit: String },
+    FetchError { repo: &'a str, error: String },
+    RunStarted {
+        repo: &'a str,
+        commit: &'a str,
+        /// Whether the run was requested with SIGUSR1.
+        rerun: bool,
+    },
+    RunFinished {
+        repo: &'a str,
+        commit: &'a str,
+        outcome: RunOutcome,
+    },
+    JobStarted {
+        repo: &'a str,
+        commit: &'a str,
+        job: &'a str,
+        attempt: u32,
+        attempts: u32,
+    },
+    /// An attempt of a job ended, `retry_in_secs` is set if it is retried.
+    JobFinished {
+        repo: &'a str,
+        commit: &'a str,
+        job: &'a str,
+        attempt: u32,
+        attempts: u32,
+        outcome: JobOutcome,
+        duration_secs: f64,
+        exit_code: Option<i32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        retry_in_secs: Option<u64>,
+    },
+    JobSkipped {
+        repo: &'a str,
+        commit: &'a str,
+        job: &'a str,
+        reason: String,
+    },
+    JobOutput {
+        repo: &'a str,
+        commit: &'a str,
+        job: &'a str,
+        stream: Stream,
+        line: &'a str,
+    },
+    Message {
+        #[serde(skip)]
+        level: Level,
+        #[serde(flatten)]
+        scope: Scope<'a>,
+        message: String,
+    },
+}
+
+impl Event<'_> {
+    fn level(&self) -> Level {
+        match self {
+            Event::FetchError { .. } => Level::Error,
+            Event::RunFinished { outcome, .. } => match outcome {
+                RunOutcome::Running | RunOutcome::Succeeded => Level::Info,
+                RunOutcome::Canceled => Level::Warn,
+                RunOutcome::Failed => Level::Error,
+            },
+            Event::JobFinished { outcome, .. } => match outcome {
+                JobOutcome::Succeeded => Level::Info,
+                JobOutcome::Canceled => Level::Warn,
+                JobOutcome::Failed | JobOutcome::TimedOut => Level::Error,
+            },
+            Event::Message { level, .. } => *level,
+            _ => Level::Info,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    /// Unix timestamp with millisecond precision.
+    time: f64,
+    level: Level,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+/// Logs `event` in the JSON format, or `text` if there is one in the text format.
+pub fn event<T: Display>(event: Event, text: Option<StyledContent<T>>) {
+    match format() {
+        LogFormat::Text => {
+            if let Some(text) = text {
+                println!("{}", text);
+            }
+        }
+        LogFormat::Json => {
+            let time = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_millis() as f64 / 1000.0)
+                .unwrap_or_default();
+            let record = Record {
+                time,
+                level: event.level(),
+                event: &event,
+            };
+            if let Ok(line) = serde_json::to_string(&record) {
+                println!("{}", line);
+            }
+        }
+    }
+}
+
+/// Logs a message that is not one of the structured events.
+pub fn message<T: Display>(level: Level, scope: Scope, text: StyledContent<T>) {
+    let message = text.content().to_string();
+    event(Event::Message { level, scope, message }, Some(text));
+}
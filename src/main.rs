@@ -1,138 +1,563 @@
 mod cli;
+mod control;
+mod daemon_config;
 mod forge;
 mod git;
+mod gitea;
 mod github;
+mod gitlab;
+mod log;
+mod metrics;
+#[cfg(test)]
+mod mock_server;
+mod plain_git;
 mod runner;
+mod secrets;
+mod state;
 mod workflow_config;
 
-use anyhow::Result;
-use clap::Parser;
-use crossterm::style::Stylize;
+use anyhow::{Result, anyhow};
+use clap::{CommandFactory, FromArgMatches};
+use crossterm::style::{StyledContent, Stylize};
 use gethostname::gethostname;
+use gitea::Gitea;
 use github::GitHub;
-use signal_hook::{consts::{SIGINT, SIGTERM}, iterator::Signals};
-use std::{path::PathBuf, process, sync::{Arc, mpsc::{self, Receiver}}, thread, time::Duration};
+use gitlab::GitLab;
+use itertools::Itertools;
+use plain_git::PlainGit;
+use signal_hook::{consts::{SIGINT, SIGTERM, SIGUSR1}, iterator::Signals};
+use std::{collections::{HashMap, HashSet}, fmt::Display, path::{Path, PathBuf}, process, sync::{Arc, Mutex, mpsc::{self, Receiver, RecvTimeoutError}}, thread, time::Duration};
 
-use crate::{cli::Cli, forge::Forge, git::GitRepo, runner::Runner};
+use crate::{cli::{Backend, Cli, Command, ConfigCommand}, control::RepoStatus, daemon_config::DaemonConfig, forge::{Forge, StatusState}, git::GitRepo, log::{Event, Level, Scope}, metrics::RepoMetrics, runner::{Runner, RunnerConfig}, secrets::SecretStore, state::StateStore};
 
 fn main() -> Result<()> {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches)?;
+    let config = match &cli.config {
+        Some(config_path) => DaemonConfig::load(config_path)?,
+        None => DaemonConfig::default(),
+    };
+    config.apply(&mut cli, &matches)?;
+    log::init(cli.log_format);
+
+    if let Some(Command::Status { json }) = &cli.command {
+        let statuses = control::request_status(&cli.control_socket)?;
+        if *json {
+            println!("{}", serde_json::to_string_pretty(&statuses)?);
+        } else {
+            control::print_status(&statuses);
+        }
+        return Ok(());
+    }
+
     let host_identifier = cli
         .host_identifier
+        .clone()
         .unwrap_or_else(|| {
             gethostname()
                 .into_string()
                 .expect("Failed to get hostname, maybe specify host_identifier manually")
-        })
-        .clone();
+        });
 
-    let github_token = if let Some(token_file) = &cli.github_token_file {
-        let token = std::fs::read_to_string(token_file)?;
-        token.trim().to_owned()
-    } else {
-        cli.github_token.expect("No GitHub token provided")
-    };
+    let host_labels = read_host_labels(&cli.host_labels, cli.host_labels_file.as_deref())?;
 
-    let checkout_path = cli.checkout_path.unwrap_or_else(|| {
-        PathBuf::from("/var/pulld/repos").join(&cli.owner).join(&cli.repo)
-    });
+    let repos = prepare_repos(&config.repos(&cli, &matches)?)?;
 
-    let gh = GitHub::new(&cli.owner, &cli.repo, &github_token)?;
-    let ssh_url = gh.git_ssh_url();
-    let git_repo = git::GitRepo::new(&checkout_path, &ssh_url, &cli.branch, &cli.ssh_key_path);
+    if let Some(Command::Config { command: ConfigCommand::Check }) = &cli.command {
+        println!("Host {} with labels {:?}", host_identifier, host_labels);
+        for repo in &repos {
+            println!(
+                "Watching {} branch {} in {}",
+                git::redact_url(&repo.forge.git_ssh_url()),
+                repo.settings.branch,
+                repo.checkout_path.display()
+            );
+        }
+        println!("{}", "Config is valid".bold().green());
+        return Ok(());
+    }
 
-    let (shutdown_sender, shutdown_receiver) = mpsc::channel();
-    let mut poller = Poller::new(git_repo, Arc::new(gh), host_identifier, cli.poll_interval, shutdown_receiver)?;
+    let mut pollers = Vec::new();
+    let mut poller_senders = Vec::new();
+    for repo in repos {
+        let (poller_sender, poller_receiver) = mpsc::channel();
+        pollers.push(build_poller(repo, &host_identifier, &host_labels, poller_receiver)?);
+        poller_senders.push(poller_sender);
+    }
 
-    // signals handling
-    let mut signals = Signals::new(&[SIGTERM, SIGINT])?;
+    let statuses = pollers.iter().map(Poller::status).collect();
+    if let Some(metrics_listen) = &cli.metrics_listen {
+        let metrics = pollers.iter().map(Poller::metrics).collect();
+        metrics::serve(metrics_listen, metrics)?;
+    }
+
+    let control_socket_bound = match control::serve(&cli.control_socket, statuses) {
+        Ok(()) => true,
+        Err(err) => {
+            log::message(
+                Level::Error,
+                Scope::default(),
+                format!("Control socket disabled: {}", err).bold().red(),
+            );
+            false
+        }
+    };
+
+    // signals handling, every poller receives the signals
+    let mut signals = Signals::new([SIGTERM, SIGINT, SIGUSR1])?;
     let handle = signals.handle();
     thread::spawn(move || {
+        let send_to_pollers = |msg: PollerMsg| {
+            for poller_sender in &poller_senders {
+                // a poller that stopped with an error can't receive anymore
+                let _ = poller_sender.send(msg.clone());
+            }
+        };
+
+        let info = |text: &str| log::message(Level::Info, Scope::default(), text.stylize());
         for signal in signals.forever() {
-            println!("Received signal {}", signal);
+            info(&format!("Received signal {}", signal));
             match signal {
                 SIGTERM => {
-                    println!("Received SIGTERM signal");
-                    println!("Shutting down gracefully...");
-                    shutdown_sender.send(128 + signal).unwrap();
+                    info("Received SIGTERM signal");
+                    info("Shutting down gracefully...");
+                    send_to_pollers(PollerMsg::Shutdown(128 + signal));
                 },
                 SIGINT => {
-                    println!("Received SIGINT signal");
-                    println!("Shutting down gracefully...");
-                    shutdown_sender.send(128 + signal).unwrap();
+                    info("Received SIGINT signal");
+                    info("Shutting down gracefully...");
+                    send_to_pollers(PollerMsg::Shutdown(128 + signal));
+                },
+                SIGUSR1 => {
+                    info("Received SIGUSR1 signal");
+                    send_to_pollers(PollerMsg::Rerun);
                 },
                 _ => unreachable!(),
             }
         }
     });
 
-    // main task
-    poller.run()?;
+    // main task, one thread per repository
+    let poller_handles = pollers
+        .into_iter()
+        .map(|mut poller| {
+            thread::spawn(move || {
+                poller.run().inspect_err(|err| {
+                    poller.log(Level::Error, format!("Stopped watching {}: {}", poller.url, err).bold().red());
+                })
+            })
+        })
+        .collect_vec();
+
+    let mut exit_code = 0;
+    let mut first_err = None;
+    for poller_handle in poller_handles {
+        match poller_handle.join() {
+            Ok(Ok(poller_exit_code)) => exit_code = exit_code.max(poller_exit_code),
+            Ok(Err(err)) => {
+                first_err.get_or_insert(err);
+            }
+            Err(_) => {
+                first_err.get_or_insert(anyhow!("Poller thread panicked"));
+            }
+        }
+    }
 
     // cleanup
     handle.close();
+    if control_socket_bound {
+        let _ = std::fs::remove_file(&cli.control_socket);
+    }
+
+    if let Some(err) = first_err {
+        return Err(err);
+    }
+    process::exit(exit_code);
+}
+
+struct WatchedRepo {
+    settings: Cli,
+    forge: Arc<dyn Forge>,
+    checkout_path: PathBuf,
+}
+
+/// Validates the settings of the watched repositories and sets up their forges, without touching
+/// the checkouts.
+fn prepare_repos(repo_clis: &[Cli]) -> Result<Vec<WatchedRepo>> {
+    let mut checkout_paths = HashSet::new();
+    let mut repos = Vec::new();
+
+    for repo_cli in repo_clis {
+        let forge = build_forge(repo_cli)?;
+        if repo_cli.backend == Some(Backend::Git) && !repo_cli.required_contexts.is_empty() {
+            return Err(anyhow!("Required contexts are not supported by the git backend"));
+        }
+        let checkout_path = checkout_path(repo_cli, forge.as_ref());
+        if !checkout_paths.insert(checkout_path.clone()) {
+            return Err(anyhow!(
+                "Multiple repositories use the checkout path {}",
+                checkout_path.display()
+            ));
+        }
+        repos.push(WatchedRepo {
+            settings: repo_cli.clone(),
+            forge,
+            checkout_path,
+        });
+    }
+
+    Ok(repos)
+}
 
-    Ok(())
+fn checkout_path(cli: &Cli, forge: &dyn Forge) -> PathBuf {
+    match (&cli.checkout_path, &cli.owner, &cli.repo) {
+        (Some(path), _, _) => path.clone(),
+        (None, Some(owner), Some(repo)) => PathBuf::from("/var/pulld/repos").join(owner).join(repo),
+        (None, _, _) => PathBuf::from("/var/pulld/repos").join(repo_name_from_url(&forge.git_ssh_url())),
+    }
+}
+
+/// Sets up the checkout and runner of a watched repository.
+fn build_poller(
+    repo: WatchedRepo,
+    host_identifier: &str,
+    host_labels: &HashMap<String, String>,
+    control_rx: Receiver<PollerMsg>,
+) -> Result<Poller> {
+    let WatchedRepo {
+        settings: cli,
+        forge,
+        checkout_path,
+    } = repo;
+    let ssh_url = forge.git_ssh_url();
+    let git_repo = git::GitRepo::new(&checkout_path, &ssh_url, &cli.branch, cli.ssh_key_path.as_deref());
+
+    Poller::new(
+        git_repo,
+        forge,
+        host_identifier.to_owned(),
+        cli.required_contexts,
+        RunnerConfig {
+            max_parallel_jobs: cli.max_parallel_jobs,
+            host_labels: host_labels.clone(),
+            secrets: SecretStore {
+                dir: cli.secrets_dir,
+                env_file: cli.secrets_file,
+            },
+            job_timeout: cli.job_timeout,
+            termination_grace_period: Duration::from_secs(cli.termination_grace_period),
+        },
+        cli.poll_interval,
+        control_rx,
+    )
+}
+
+fn build_forge(cli: &Cli) -> Result<Arc<dyn Forge>> {
+    let backend = cli.backend.ok_or_else(|| anyhow!("No backend provided"))?;
+    if backend == Backend::Git {
+        let url = cli
+            .git_url
+            .as_deref()
+            .ok_or_else(|| anyhow!("No git URL provided"))?;
+        return Ok(Arc::new(PlainGit::new(url)?));
+    }
+
+    let owner = cli.owner.as_deref().ok_or_else(|| anyhow!("No owner provided"))?;
+    let repo = cli.repo.as_deref().ok_or_else(|| anyhow!("No repo provided"))?;
+
+    let forge: Arc<dyn Forge> = match backend {
+        Backend::Github => {
+            if let Some(app_id) = &cli.github_app_id {
+                let key_file = cli
+                    .github_app_private_key_file
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("No GitHub App private key provided"))?;
+                let private_key = std::fs::read_to_string(key_file)?;
+                Arc::new(GitHub::new_app(
+                    &cli.github_api_url,
+                    &cli.github_ssh_host,
+                    owner,
+                    repo,
+                    app_id,
+                    &private_key,
+                    cli.github_app_installation_id,
+                )?
+                .with_check_runs(cli.github_check_runs)?)
+            } else {
+                let token = read_token(cli.github_token.as_deref(), cli.github_token_file.as_deref())?
+                    .ok_or_else(|| anyhow!("No GitHub token provided"))?;
+                Arc::new(
+                    GitHub::new(&cli.github_api_url, &cli.github_ssh_host, owner, repo, &token)?
+                        .with_check_runs(cli.github_check_runs)?,
+                )
+            }
+        }
+        Backend::Gitlab => {
+            let token = read_token(cli.gitlab_token.as_deref(), cli.gitlab_token_file.as_deref())?
+                .ok_or_else(|| anyhow!("No GitLab token provided"))?;
+            Arc::new(GitLab::new(
+                &cli.gitlab_url,
+                cli.gitlab_ssh_host.as_deref(),
+                owner,
+                repo,
+                &token,
+            )?)
+        }
+        Backend::Gitea => {
+            let base_url = cli
+                .gitea_url
+                .as_deref()
+                .ok_or_else(|| anyhow!("No Gitea URL provided"))?;
+            let token = read_token(cli.gitea_token.as_deref(), cli.gitea_token_file.as_deref())?
+                .ok_or_else(|| anyhow!("No Gitea token provided"))?;
+            Arc::new(Gitea::new(
+                base_url,
+                cli.gitea_ssh_host.as_deref(),
+                owner,
+                repo,
+                &token,
+            )?)
+        }
+        Backend::Git => unreachable!(),
+    };
+
+    Ok(forge)
+}
+
+/// Derives a directory name from a git URL, e.g. `git@host:infra/deploy.git` becomes `deploy`.
+fn repo_name_from_url(url: &str) -> &str {
+    let name = url
+        .trim_end_matches('/')
+        .rsplit(['/', ':'])
+        .next()
+        .unwrap_or(url);
+    name.strip_suffix(".git").unwrap_or(name)
+}
+
+/// Reads a token from `token_file` if given, falling back to the plain `token` value.
+fn read_token(token: Option<&str>, token_file: Option<&Path>) -> Result<Option<String>> {
+    if let Some(token_file) = token_file {
+        let token = std::fs::read_to_string(token_file)?;
+        Ok(Some(token.trim().to_owned()))
+    } else {
+        Ok(token.map(ToOwned::to_owned))
+    }
+}
+
+/// Collects the labels of the local host from `KEY=VALUE` arguments and an optional labels file,
+/// which contains one label per line. Labels given as arguments take precedence.
+fn read_host_labels(labels: &[String], labels_file: Option<&Path>) -> Result<HashMap<String, String>> {
+    let file_content = match labels_file {
+        Some(labels_file) => std::fs::read_to_string(labels_file)?,
+        None => String::new(),
+    };
+    let file_labels = file_content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+    file_labels
+        .chain(labels.iter().map(String::as_str))
+        .map(|label| {
+            let (key, value) = label
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid host label {}, expected KEY=VALUE", label))?;
+            Ok((key.trim().to_owned(), value.trim().to_owned()))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+enum PollerMsg {
+    /// Exit with the given code once the current run finished.
+    Shutdown(i32),
+    /// Run the jobs of the checked out commit again.
+    Rerun,
 }
 
 struct Poller {
     repo: GitRepo,
+    /// URL of the watched repository, looked up once for logging.
+    url: String,
     current_commit_id: git2::Oid,
+    /// Newest commit that is waiting for its required contexts to succeed.
+    gated_commit_id: Option<git2::Oid>,
+    forge: Arc<dyn Forge>,
     runner: Runner,
     host_identifier: String,
-    shutdown_rx: Receiver<i32>,
+    required_contexts: Vec<String>,
+    control_rx: Receiver<PollerMsg>,
     poll_interval: u64,
+    status: Arc<Mutex<RepoStatus>>,
+    metrics: Arc<Mutex<RepoMetrics>>,
 }
 
 impl Poller {
-    fn new(repo: GitRepo, forge: Arc<dyn Forge>, host_identifier: String, poll_interval: u64, shutdown_rx: Receiver<i32>) -> Result<Self> {
-        let current_commit_id = repo.current_commit()?.id();
+    fn new(
+        repo: GitRepo,
+        forge: Arc<dyn Forge>,
+        host_identifier: String,
+        required_contexts: Vec<String>,
+        runner_config: RunnerConfig,
+        poll_interval: u64,
+        control_rx: Receiver<PollerMsg>,
+    ) -> Result<Self> {
+        let state = StateStore::load(&repo.git_dir().join("pulld-state.json"))?;
+        let interrupted_commit = state
+            .state()
+            .run_interrupted()
+            .then(|| state.state().last_attempted_commit.clone())
+            .flatten();
+        let never_deployed = state.state().last_attempted_commit.is_none();
+        // the URL ends up in logs, the status and metrics labels
+        let url = git::redact_url(&repo.url());
+        let status = Arc::new(Mutex::new(RepoStatus {
+            url: url.clone(),
+            branch: repo.branch().to_owned(),
+            checkout_path: repo.path().to_owned(),
+            current_commit: repo.current_commit().ok().map(|commit| commit.id().to_string()),
+            last_run_outcome: state.state().last_run_outcome,
+            ..Default::default()
+        }));
+        let metrics = Arc::new(Mutex::new(RepoMetrics {
+            url: url.clone(),
+            branch: repo.branch().to_owned(),
+            last_successful_run_at: state.state().last_successful_run_at,
+            current_commit: repo.current_commit().ok().map(|commit| commit.id().to_string()),
+            ..Default::default()
+        }));
+        let runner = Runner::new(
+            url.clone(),
+            forge.clone(),
+            Arc::new(Mutex::new(state)),
+            runner_config,
+            status.clone(),
+            metrics.clone(),
+        );
+
+        let scope = Scope { repo: Some(&url), job: None };
+        let current_commit_id = if let Some(sha) = interrupted_commit {
+            log::message(
+                Level::Warn,
+                scope,
+                format!("Run for {} was interrupted, retrying...", sha)
+                    .bold()
+                    .dark_yellow(),
+            );
+            if let Err(err) = runner.mark_interrupted_jobs(&sha, &host_identifier) {
+                log::message(
+                    Level::Error,
+                    scope,
+                    format!("Failed to update statuses of interrupted jobs: {}", err).stylize(),
+                );
+            }
+            // forces a new run on the first poll
+            git2::Oid::zero()
+        } else if never_deployed {
+            // e.g. a fresh clone, its commit was checked out but never deployed
+            log::message(
+                Level::Info,
+                scope,
+                "No deployment recorded yet, deploying the newest commit...".stylize(),
+            );
+            git2::Oid::zero()
+        } else {
+            repo.current_commit()?.id()
+        };
 
         Ok(Poller {
             repo,
+            url,
             current_commit_id,
-            runner: Runner::new(forge),
+            gated_commit_id: None,
+            forge,
+            runner,
             host_identifier,
+            required_contexts,
             poll_interval,
-            shutdown_rx,
+            control_rx,
+            status,
+            metrics,
         })
     }
 
-    pub fn run(&mut self) -> Result<()> {
-        println!("👀 Watching for changes at {}...", self.repo.url());
+    /// Logs a message about the watched repository.
+    fn log<T: Display>(&self, level: Level, text: StyledContent<T>) {
+        log::message(level, Scope { repo: Some(&self.url), job: None }, text);
+    }
+
+    /// Status reported on the control socket.
+    fn status(&self) -> Arc<Mutex<RepoStatus>> {
+        self.status.clone()
+    }
+
+    /// Metrics served on `--metrics_listen`.
+    fn metrics(&self) -> Arc<Mutex<RepoMetrics>> {
+        self.metrics.clone()
+    }
+
+    /// Watches the repository until pulld is shut down, returns the exit code to shut down with.
+    pub fn run(&mut self) -> Result<i32> {
+        self.log(Level::Info, format!("👀 Watching for changes at {}...", self.url).stylize());
 
         loop {
             self.poll()?;
 
-            match self.shutdown_rx.recv_timeout(Duration::from_secs(self.poll_interval)) {
-                Ok(exit_code) => {
+            self.status.lock().unwrap().next_poll = Some(control::unix_time() + self.poll_interval);
+            match self.control_rx.recv_timeout(Duration::from_secs(self.poll_interval)) {
+                Ok(PollerMsg::Rerun) => self.rerun()?,
+                Ok(PollerMsg::Shutdown(exit_code)) => {
                     if self.runner.is_running() {
-                        println!("Waiting for run to finish...");
-                        self.runner.wait_for_run()?;
+                        self.log(Level::Info, "Waiting for run to finish, send the signal again to cancel it...".stylize());
+                    }
+                    while self.runner.is_running() {
+                        if let Ok(PollerMsg::Shutdown(_)) = self.control_rx.recv_timeout(Duration::from_millis(100)) {
+                            self.log(Level::Warn, "Canceling run...".bold().dark_grey());
+                            self.runner.cancel_run()?;
+                        }
                     }
+                    self.runner.wait_for_run()?;
 
-                    process::exit(exit_code);
-                    // return Ok(());
+                    return Ok(exit_code);
                 },
-                Err(_) => continue,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(anyhow!("Signal handler stopped unexpectedly"));
+                }
             }
         }
     }
 
     fn poll(&mut self) -> Result<()> {
+        let deployed_commit_id = self.current_commit_id;
         let build_needed = {
-            let newest_commit_res = self.repo.get_newest_commit_from_remote();
+            let newest_commit_res = self.repo.get_newest_commit_from_remote().map(|commit| commit.id());
+            self.metrics.lock().unwrap().polls += 1;
             match newest_commit_res {
-                Ok(newest_commit) => {
-                    if self.current_commit_id != newest_commit.id() {
-                        self.current_commit_id = newest_commit.id();
+                Ok(newest_commit_id) => {
+                    log::event::<&str>(
+                        Event::Poll {
+                            repo: &self.url,
+                            commit: newest_commit_id.to_string(),
+                        },
+                        None,
+                    );
+                    if self.current_commit_id != newest_commit_id && self.required_contexts_passed(newest_commit_id) {
+                        self.current_commit_id = newest_commit_id;
                         true
                     } else {
                         false
                     }
                 }
                 Err(err) => {
-                    println!("Error fetching newest commit: {}", err);
+                    self.metrics.lock().unwrap().fetch_errors += 1;
+                    log::event(
+                        Event::FetchError {
+                            repo: &self.url,
+                            error: err.to_string(),
+                        },
+                        Some(format!("Error fetching newest commit: {}", err).stylize()),
+                    );
                     false
                 }
             }
@@ -140,19 +565,102 @@ impl Poller {
 
         if build_needed {
             if self.runner.is_running() {
-                println!(
-                    "{}",
-                    "New commit, canceling current run...".bold().dark_grey()
-                );
+                self.log(Level::Info, "New commit, canceling current run...".bold().dark_grey());
                 self.runner.cancel_run()?;
             }
 
-            let run_res = self.runner.start_run(&self.repo, self.current_commit_id, &self.host_identifier);
+            let run_res = self.runner.start_run(&self.repo, self.current_commit_id, &self.host_identifier, false);
             if let Err(err) = run_res {
-                println!("{}", format!("Failed to start run: {}", err).bold().red());
+                self.log(Level::Error, format!("Failed to start run: {}", err).bold().red());
+                // the commit was not deployed, try again on the next poll
+                self.current_commit_id = deployed_commit_id;
             }
         }
 
         Ok(())
     }
+
+    /// Runs all jobs of the checked out commit again, e.g. after fixing a problem on the host.
+    fn rerun(&mut self) -> Result<()> {
+        if self.runner.is_running() {
+            self.log(Level::Info, "A run is in progress, ignoring re-run request".bold().dark_grey());
+            return Ok(());
+        }
+
+        let commit_id = self.repo.current_commit()?.id();
+        self.log(Level::Info, format!("Re-running {}...", commit_id).bold().dark_yellow());
+        let run_res = self.runner.start_run(&self.repo, commit_id, &self.host_identifier, true);
+        if let Err(err) = run_res {
+            self.log(Level::Error, format!("Failed to start run: {}", err).bold().red());
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether the required contexts succeeded on `commit_id`. Commits with failed
+    /// contexts are skipped, commits with pending contexts are checked again on the next poll.
+    fn required_contexts_passed(&mut self, commit_id: git2::Oid) -> bool {
+        if self.required_contexts.is_empty() {
+            return true;
+        }
+
+        let sha = commit_id.to_string();
+        match forge::required_contexts_state(self.forge.as_ref(), &sha, &self.required_contexts) {
+            Ok((StatusState::Success, _)) => true,
+            Ok((StatusState::Pending, pending)) => {
+                if self.gated_commit_id != Some(commit_id) {
+                    self.gated_commit_id = Some(commit_id);
+                    let contexts = pending.iter().filter_map(|status| status.context.as_deref()).join(", ");
+                    self.log(
+                        Level::Info,
+                        format!("Waiting for {} on {}...", contexts, sha)
+                            .bold()
+                            .dark_grey(),
+                    );
+                }
+                false
+            }
+            Ok((_, unsuccessful)) => {
+                self.log(
+                    Level::Warn,
+                    format!("Skipping {}, required checks did not succeed", sha)
+                        .bold()
+                        .red(),
+                );
+                for status in unsuccessful {
+                    self.log(
+                        Level::Warn,
+                        format!(
+                            "  {}: {:?} {} {}",
+                            status.context.unwrap_or_default(),
+                            status.state,
+                            status.description.unwrap_or_default(),
+                            status.target_url.unwrap_or_default()
+                        )
+                        .stylize(),
+                    );
+                }
+                self.current_commit_id = commit_id;
+                false
+            }
+            Err(err) => {
+                self.log(Level::Error, format!("Error fetching statuses of {}: {}", sha, err).stylize());
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repo_name_from_url_strips_path_and_git_suffix() {
+        assert_eq!(repo_name_from_url("git@github.com:owner/repo.git"), "repo");
+        assert_eq!(repo_name_from_url("https://gitlab.example.com/group/sub/repo/"), "repo");
+        assert_eq!(repo_name_from_url("ssh://git@example.com:2222/srv/repo.git"), "repo");
+        assert_eq!(repo_name_from_url("/srv/git/repo"), "repo");
+        assert_eq!(repo_name_from_url("git@host:repo.git"), "repo");
+    }
 }
@@ -0,0 +1,246 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+use crossterm::style::Stylize;
+
+use crate::{
+    control::outcome_name,
+    log::{self, Level, Scope},
+    runner::JobOutcome,
+    state::RunOutcome,
+};
+
+/// Upper bounds of the job duration histogram buckets in seconds.
+const DURATION_BUCKETS: [f64; 10] = [1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0, 3600.0];
+
+/// Counters and gauges of a watched repository, exposed in the Prometheus text format.
+#[derive(Debug, Default)]
+pub struct RepoMetrics {
+    pub url: String,
+    pub branch: String,
+    pub polls: u64,
+    pub fetch_errors: u64,
+    pub runs_started: u64,
+    /// Finished runs by outcome.
+    pub runs: BTreeMap<String, u64>,
+    pub jobs: BTreeMap<String, JobMetrics>,
+    /// Unix timestamp of the last run that succeeded.
+    pub last_successful_run_at: Option<u64>,
+    /// Commit that is checked out.
+    pub current_commit: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct JobMetrics {
+    pub started: u64,
+    /// Finished runs of the job by outcome.
+    pub finished: BTreeMap<String, u64>,
+    /// Number of durations per bucket of `DURATION_BUCKETS`, not cumulative.
+    duration_buckets: [u64; DURATION_BUCKETS.len()],
+    duration_count: u64,
+    duration_sum: f64,
+}
+
+impl RepoMetrics {
+    pub fn run_finished(&mut self, outcome: RunOutcome) {
+        *self.runs.entry(outcome_name(outcome)).or_default() += 1;
+    }
+
+    pub fn job_started(&mut self, job_name: &str) {
+        self.jobs.entry(job_name.to_owned()).or_default().started += 1;
+    }
+
+    pub fn job_finished(&mut self, job_name: &str, outcome: JobOutcome, duration: Duration) {
+        let job = self.jobs.entry(job_name.to_owned()).or_default();
+        *job.finished.entry(outcome_name(outcome)).or_default() += 1;
+
+        let secs = duration.as_secs_f64();
+        if let Some(bucket) = DURATION_BUCKETS.iter().position(|bound| secs <= *bound) {
+            job.duration_buckets[bucket] += 1;
+        }
+        job.duration_count += 1;
+        job.duration_sum += secs;
+    }
+}
+
+/// Serves the metrics of all watched repositories over HTTP at `addr` in a background thread.
+pub fn serve(addr: &str, metrics: Vec<Arc<Mutex<RepoMetrics>>>) -> Result<()> {
+    let listener = TcpListener::bind(addr)
+        .map_err(|err| anyhow!("Couldn't listen for metrics requests on {}: {}", addr, err))?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            // a client that stalls must not block other scrapes
+            let metrics = metrics.clone();
+            thread::spawn(move || {
+                let res = stream
+                    .map_err(Into::into)
+                    .and_then(|stream| handle_request(stream, &metrics));
+                if let Err(err) = res {
+                    log::message(
+                        Level::Error,
+                        Scope::default(),
+                        format!("Failed to handle metrics request: {}", err).stylize(),
+                    );
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+fn handle_request(mut stream: TcpStream, metrics: &[Arc<Mutex<RepoMetrics>>]) -> Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // the headers are not needed, but have to be read before responding
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", render(metrics)),
+        (Some("GET"), _) => ("404 Not Found", "Metrics are served at /metrics\n".to_owned()),
+        _ => ("405 Method Not Allowed", String::new()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+/// A sample of a metric: suffix of the metric name, labels besides `repo` and value.
+type Sample = (&'static str, String, String);
+
+/// Renders the metrics in the Prometheus text format.
+fn render(metrics: &[Arc<Mutex<RepoMetrics>>]) -> String {
+    let metrics = metrics
+        .iter()
+        .map(|metrics| metrics.lock().unwrap())
+        .collect::<Vec<_>>();
+    let mut out = String::new();
+
+    let mut family = |name: &str, kind: &str, help: &str, samples: &dyn Fn(&RepoMetrics) -> Vec<Sample>| {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} {kind}");
+        for repo in &metrics {
+            for (suffix, labels, value) in samples(repo) {
+                let _ = writeln!(out, "{name}{suffix}{{repo=\"{}\"{labels}}} {value}", escape(&repo.url));
+            }
+        }
+    };
+
+    family("pulld_polls_total", "counter", "Polls of the watched branch.", &|repo| {
+        vec![("", String::new(), repo.polls.to_string())]
+    });
+    family(
+        "pulld_fetch_errors_total",
+        "counter",
+        "Polls that failed to fetch the watched branch.",
+        &|repo| vec![("", String::new(), repo.fetch_errors.to_string())],
+    );
+    family("pulld_runs_started_total", "counter", "Runs that were started.", &|repo| {
+        vec![("", String::new(), repo.runs_started.to_string())]
+    });
+    family("pulld_runs_total", "counter", "Runs that finished, by outcome.", &|repo| {
+        repo.runs
+            .iter()
+            .map(|(outcome, count)| ("", format!(",outcome=\"{outcome}\""), count.to_string()))
+            .collect()
+    });
+    family("pulld_job_runs_started_total", "counter", "Jobs that were started.", &|repo| {
+        repo.jobs
+            .iter()
+            .map(|(job_name, job)| ("", job_label(job_name), job.started.to_string()))
+            .collect()
+    });
+    family("pulld_job_runs_total", "counter", "Jobs that finished, by outcome.", &|repo| {
+        repo.jobs
+            .iter()
+            .flat_map(|(job_name, job)| {
+                job.finished.iter().map(move |(outcome, count)| {
+                    let labels = format!("{},outcome=\"{outcome}\"", job_label(job_name));
+                    ("", labels, count.to_string())
+                })
+            })
+            .collect()
+    });
+    family(
+        "pulld_job_duration_seconds",
+        "histogram",
+        "Duration of jobs including retries.",
+        &|repo| repo.jobs.iter().flat_map(|(job_name, job)| duration_samples(job_name, job)).collect(),
+    );
+    family(
+        "pulld_last_successful_run_timestamp_seconds",
+        "gauge",
+        "Unix timestamp of the last run that succeeded.",
+        &|repo| {
+            repo.last_successful_run_at
+                .map(|timestamp| ("", String::new(), timestamp.to_string()))
+                .into_iter()
+                .collect()
+        },
+    );
+    family("pulld_commit_info", "gauge", "Commit that is checked out.", &|repo| {
+        repo.current_commit
+            .iter()
+            .map(|commit| {
+                let labels = format!(",branch=\"{}\",commit=\"{}\"", escape(&repo.branch), commit);
+                ("", labels, "1".to_owned())
+            })
+            .collect()
+    });
+
+    out
+}
+
+/// Cumulative buckets, sum and count of the duration histogram of a job.
+fn duration_samples(job_name: &str, job: &JobMetrics) -> Vec<Sample> {
+    if job.duration_count == 0 {
+        return Vec::new();
+    }
+
+    let job_label = job_label(job_name);
+    let mut samples = Vec::new();
+    let mut cumulative = 0;
+    for (bound, count) in DURATION_BUCKETS.iter().zip(job.duration_buckets) {
+        cumulative += count;
+        samples.push(("_bucket", format!("{job_label},le=\"{bound}\""), cumulative.to_string()));
+    }
+    samples.push(("_bucket", format!("{job_label},le=\"+Inf\""), job.duration_count.to_string()));
+    samples.push(("_sum", job_label.clone(), job.duration_sum.to_string()));
+    samples.push(("_count", job_label, job.duration_count.to_string()));
+    samples
+}
+
+fn job_label(job_name: &str) -> String {
+    format!(",job=\"{}\"", escape(job_name))
+}
+
+/// Escapes a label value of the Prometheus text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,46 @@
+use anyhow::Result;
+use crossterm::style::Stylize;
+
+use crate::{
+    forge::{CreateStatus, Forge, Status},
+    log::{self, Level, Scope},
+};
+
+/// Backend for repositories without a forge API. Statuses are only logged locally.
+pub struct PlainGit {
+    url: String,
+}
+
+impl PlainGit {
+    pub fn new(url: &str) -> Result<PlainGit> {
+        Ok(PlainGit {
+            url: url.to_owned(),
+        })
+    }
+}
+
+impl Forge for PlainGit {
+    fn git_ssh_url(&self) -> String {
+        self.url.clone()
+    }
+
+    fn get_commit_statuses(&self, _sha: &str) -> Result<Vec<Status>> {
+        Ok(Vec::new())
+    }
+
+    fn set_commit_status(&self, sha: &str, status: CreateStatus) -> Result<()> {
+        log::message(
+            Level::Info,
+            Scope::default(),
+            format!(
+                "Status {} for {}: {:?} {}",
+                status.context,
+                sha,
+                status.state,
+                status.description.unwrap_or_default()
+            )
+            .dark_grey(),
+        );
+        Ok(())
+    }
+}
@@ -1,33 +1,80 @@
 use anyhow::{Result, anyhow};
-use crossterm::style::Stylize;
+use crossterm::style::{StyledContent, Stylize};
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 use std::{
     borrow::Cow,
+    collections::{HashMap, HashSet},
     env,
-    io::{BufRead, BufReader},
+    fmt::Display,
+    io::{BufRead, BufReader, Read},
     os::unix::process::CommandExt,
-    process::{Command, Stdio},
-    sync::{Arc, mpsc::RecvTimeoutError},
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    },
     thread::{self, JoinHandle},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use crate::{
+    control::{JobStatus, RepoStatus, unix_time},
     forge::{CreateStatus, Forge, StatusState},
     git::GitRepo,
-    workflow_config::{get_jobs_for_host, read_config},
+    log::{self, Event, Level, Scope, Stream},
+    metrics::RepoMetrics,
+    secrets::{SecretStore, mask_patterns, mask_secrets},
+    state::{DeployState, RunOutcome, StateStore},
+    workflow_config::{Job, get_jobs_for_host, parse_duration, paths_changed, read_config},
 };
 
+/// Upper bound of the delay between attempts of a job, no matter how large `backoff` is.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// Settings of the local host that apply to every run.
+pub struct RunnerConfig {
+    pub max_parallel_jobs: usize,
+    pub host_labels: HashMap<String, String>,
+    pub secrets: SecretStore,
+    /// Timeout of jobs that don't set their own.
+    pub job_timeout: Option<Duration>,
+    /// Time timed out or canceled jobs get to exit after SIGTERM before they are killed.
+    pub termination_grace_period: Duration,
+}
+
 pub struct Runner {
-    run_handle_and_sender: Option<(JoinHandle<()>, std::sync::mpsc::Sender<ToRunMsg>)>,
+    repo_url: String,
+    run_handle_and_sender: Option<(JoinHandle<()>, Sender<ToRunMsg>)>,
     forge: Arc<dyn Forge>,
+    state: Arc<Mutex<StateStore>>,
+    config: RunnerConfig,
+    status: Arc<Mutex<RepoStatus>>,
+    metrics: Arc<Mutex<RepoMetrics>>,
 }
 
 impl Runner {
-    pub fn new(forge: Arc<dyn Forge>) -> Self {
+    pub fn new(
+        repo_url: String,
+        forge: Arc<dyn Forge>,
+        state: Arc<Mutex<StateStore>>,
+        config: RunnerConfig,
+        status: Arc<Mutex<RepoStatus>>,
+        metrics: Arc<Mutex<RepoMetrics>>,
+    ) -> Self {
         Self {
+            repo_url,
             run_handle_and_sender: None,
             forge,
+            state,
+            status,
+            metrics,
+            config: RunnerConfig {
+                max_parallel_jobs: config.max_parallel_jobs.max(1),
+                ..config
+            },
         }
     }
 
@@ -41,20 +88,21 @@ impl Runner {
 
     pub fn wait_for_run(&mut self) -> Result<()> {
         if let Some((handle, _)) = self.run_handle_and_sender.take() {
-            handle.join().map_err(|err| anyhow!("asd"))?;
+            handle.join().map_err(|_err| anyhow!("Failed to wait for run"))?;
         }
 
         Ok(())
     }
 
     pub fn cancel_run(&mut self) -> Result<()> {
-        if let Some((handle, to_run)) = self.run_handle_and_sender.take() {
-            if !handle.is_finished() {
-                to_run.send(ToRunMsg::Cancel).unwrap();
-                handle
-                    .join()
-                    .map_err(|err| anyhow!("Failed to cancel run"))?;
-            }
+        if let Some((handle, to_run)) = self.run_handle_and_sender.take()
+            && !handle.is_finished()
+        {
+            // the run may have finished in the meantime
+            let _ = to_run.send(ToRunMsg::Cancel);
+            handle
+                .join()
+                .map_err(|_err| anyhow!("Failed to cancel run"))?;
         }
         Ok(())
     }
@@ -64,23 +112,191 @@ impl Runner {
         repo: &GitRepo,
         commit_id: git2::Oid,
         host_identifier: &str,
+        force: bool,
     ) -> Result<()> {
-        let (to_run_tx, to_run_rx) = std::sync::mpsc::channel::<ToRunMsg>();
+        let (to_run_tx, to_run_rx) = mpsc::channel::<ToRunMsg>();
 
-        println!(
-            "{}",
-            format!("Starting run for {}...", commit_id)
-                .bold()
-                .dark_yellow()
+        log::event(
+            Event::RunStarted {
+                repo: &self.repo_url,
+                commit: &commit_id.to_string(),
+                rerun: force,
+            },
+            Some(
+                format!("Starting run for {}...", commit_id)
+                    .bold()
+                    .dark_yellow(),
+            ),
         );
 
+        // record the attempt before touching the checkout, so an interrupted run is retried
+        update_state(&self.state, |state| {
+            state.last_attempted_commit = Some(commit_id.to_string());
+            state.last_run_outcome = Some(RunOutcome::Running);
+        });
+        {
+            let mut status = self.status.lock().unwrap();
+            status.running_commit = Some(commit_id.to_string());
+            status.last_run_outcome = Some(RunOutcome::Running);
+        }
+        self.metrics.lock().unwrap().runs_started += 1;
+
+        let jobs = match self.prepare_run(repo, commit_id, host_identifier, force) {
+            Ok(jobs) => jobs,
+            Err(err) => {
+                update_state(&self.state, |state| {
+                    state.last_run_outcome = Some(RunOutcome::Failed);
+                });
+                let mut status = self.status.lock().unwrap();
+                status.running_commit = None;
+                status.last_run_outcome = Some(RunOutcome::Failed);
+                self.metrics.lock().unwrap().run_finished(RunOutcome::Failed);
+                return Err(err);
+            }
+        };
+        self.status.lock().unwrap().current_commit = Some(commit_id.to_string());
+        self.metrics.lock().unwrap().current_commit = Some(commit_id.to_string());
+
+        let run = Arc::new(RunContext {
+            forge: self.forge.clone(),
+            state: self.state.clone(),
+            commit_id,
+            repo_url: self.repo_url.clone(),
+            host_identifier: host_identifier.to_owned(),
+            repo_path: repo.path().to_owned(),
+            branch: repo.branch().to_owned(),
+            secrets: self.config.secrets.clone(),
+            job_timeout: self.config.job_timeout,
+            termination_grace_period: self.config.termination_grace_period,
+            prefix_output: self.config.max_parallel_jobs > 1,
+            status: self.status.clone(),
+            metrics: self.metrics.clone(),
+        });
+        let max_parallel_jobs = self.config.max_parallel_jobs;
+        let job_finished_tx = to_run_tx.clone();
+
+        let run_handle = thread::spawn(move || {
+            run.run_jobs(jobs, max_parallel_jobs, job_finished_tx, to_run_rx);
+        });
+
+        self.run_handle_and_sender = Some((run_handle, to_run_tx));
+
+        Ok(())
+    }
+
+    /// Marks jobs of this host that are still pending on `sha` as interrupted. Used after a
+    /// restart, when the run that reported them can no longer finish them.
+    pub fn mark_interrupted_jobs(&self, sha: &str, host_identifier: &str) -> Result<()> {
+        let host_suffix = format!("/{}", host_identifier);
+        // forges return the latest status of every context, a job reported both as commit
+        // status and check run is only looked at once
+        let statuses = self
+            .forge
+            .get_commit_statuses(sha)?
+            .into_iter()
+            .chain(self.forge.get_check_runs(sha)?)
+            .unique_by(|status| status.context.clone());
+
+        for status in statuses {
+            let Some(job_name) = status.context.as_deref().and_then(|context| {
+                context.strip_prefix("pulld/")?.strip_suffix(&host_suffix)
+            }) else {
+                continue;
+            };
+
+            if matches!(status.state, StatusState::Pending | StatusState::Running) {
+                self.forge.set_commit_status(
+                    sha,
+                    CreateStatus {
+                        state: StatusState::Error,
+                        description: Some(format!(
+                            "Job {job_name} on host {host_identifier} was interrupted by restart"
+                        )),
+                        context: format!("pulld/{}/{}", job_name, host_identifier),
+                        target_url: None,
+                        output: None,
+                    },
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks out `commit_id` and marks the jobs of this host as pending. Unless `force` is set,
+    /// jobs whose watched paths did not change since their last successful run are skipped.
+    fn prepare_run(
+        &self,
+        repo: &GitRepo,
+        commit_id: git2::Oid,
+        host_identifier: &str,
+        force: bool,
+    ) -> Result<Vec<(String, Job)>> {
         repo.reset_hard(commit_id)?;
 
         let workflow_config = read_config(repo.path())?;
-        let jobs = get_jobs_for_host(&workflow_config, host_identifier)?;
+        let mut jobs = get_jobs_for_host(&workflow_config, host_identifier, &self.config.host_labels)?;
 
-        for job_name in jobs.keys() {
+        let mut unchanged_jobs = HashSet::new();
+        for (job_name, job) in &jobs {
+            if force || (job.paths.is_empty() && job.paths_ignore.is_empty()) {
+                continue;
+            }
+            let last_successful_commit = self
+                .state
+                .lock()
+                .unwrap()
+                .state()
+                .last_successful_commits
+                .get(job_name)
+                .and_then(|sha| git2::Oid::from_str(sha).ok());
+            // without a previous deployment, or if it is gone after a force push, run the job
+            let Some(changed_files) =
+                last_successful_commit.and_then(|from| repo.changed_files(from, commit_id).ok())
+            else {
+                continue;
+            };
+            if !paths_changed(job, &changed_files)? {
+                unchanged_jobs.insert(job_name.clone());
+            }
+        }
+
+        for job_name in &unchanged_jobs {
+            log::event(
+                Event::JobSkipped {
+                    repo: &self.repo_url,
+                    commit: &commit_id.to_string(),
+                    job: job_name,
+                    reason: "no watched files changed".to_owned(),
+                },
+                Some(
+                    format!("Skipping job {job_name}, no watched files changed")
+                        .bold()
+                        .dark_grey(),
+                ),
+            );
+            update_state(&self.state, |state| {
+                state
+                    .last_successful_commits
+                    .insert(job_name.clone(), commit_id.to_string());
+            });
             self.forge.set_commit_status(
+                &commit_id.to_string(),
+                CreateStatus {
+                    state: StatusState::Success,
+                    description: Some(format!(
+                        "Job {job_name} on host {host_identifier} was skipped, no watched files changed"
+                    )),
+                    context: format!("pulld/{}/{}", job_name, host_identifier),
+                    target_url: None,
+                    output: None,
+                },
+            )?;
+        }
+        jobs.retain(|(job_name, _)| !unchanged_jobs.contains(job_name));
+
+        for (i, (job_name, _)) in jobs.iter().enumerate() {
+            let res = self.forge.set_commit_status(
                 &commit_id.to_string(),
                 CreateStatus {
                     state: StatusState::Pending,
@@ -89,174 +305,1017 @@ impl Runner {
                     )),
                     context: format!("pulld/{}/{}", job_name, host_identifier),
                     target_url: None,
+                    output: None,
                 },
-            )?;
+            );
+            if let Err(err) = res {
+                // the run doesn't start, so the jobs marked pending so far would stay pending
+                self.mark_jobs_not_started(&jobs[..i], commit_id, host_identifier, &err);
+                return Err(err);
+            }
         }
 
-        let forge = self.forge.clone();
-        let host_identifier = host_identifier.to_owned();
-        let repo_path = repo.path().to_owned();
+        Ok(jobs)
+    }
 
-        let run_handle = thread::spawn(move || {
-            let mut job_iter = jobs.into_iter();
-            while let Some((job_name, job)) = job_iter.next() {
-                let mut job_failed = false;
-                let mut job_canceled = false;
-                let mut output = String::new();
+    fn mark_jobs_not_started(
+        &self,
+        jobs: &[(String, Job)],
+        commit_id: git2::Oid,
+        host_identifier: &str,
+        err: &anyhow::Error,
+    ) {
+        for (job_name, _) in jobs {
+            let res = self.forge.set_commit_status(
+                &commit_id.to_string(),
+                CreateStatus {
+                    state: StatusState::Error,
+                    description: Some(format!(
+                        "Job {job_name} on host {host_identifier} could not be started: {err}"
+                    )),
+                    context: format!("pulld/{}/{}", job_name, host_identifier),
+                    target_url: None,
+                    output: None,
+                },
+            );
+            if let Err(err) = res {
+                log::message(
+                    Level::Error,
+                    Scope { repo: Some(&self.repo_url), job: Some(job_name) },
+                    format!("Failed to set status of job {job_name}: {}", err).stylize(),
+                );
+            }
+        }
+    }
+}
 
-                println!("{}", format!("Running job {job_name}...").bold());
+/// Persists a change to the deploy state, failing to do so should not abort a run.
+fn update_state(state: &Mutex<StateStore>, change: impl FnOnce(&mut DeployState)) {
+    if let Err(err) = state.lock().unwrap().update(change) {
+        log::message(
+            Level::Error,
+            Scope::default(),
+            format!("Failed to save deploy state: {}", err).bold().red(),
+        );
+    }
+}
 
-                let _ = forge.set_commit_status(
-                    &commit_id.to_string(),
-                    CreateStatus {
-                        state: StatusState::Pending,
-                        description: Some(format!(
-                            "Job {job_name} on host {host_identifier} is running..."
-                        )),
-                        context: format!("pulld/{}/{}", job_name, host_identifier),
-                        target_url: None,
-                    },
-                );
+/// Everything the jobs of a run need, shared between the threads of the run.
+struct RunContext {
+    forge: Arc<dyn Forge>,
+    state: Arc<Mutex<StateStore>>,
+    commit_id: git2::Oid,
+    repo_url: String,
+    host_identifier: String,
+    repo_path: PathBuf,
+    branch: String,
+    secrets: SecretStore,
+    job_timeout: Option<Duration>,
+    termination_grace_period: Duration,
+    /// Prefix output lines with the job name, so output of parallel jobs can be told apart.
+    prefix_output: bool,
+    status: Arc<Mutex<RepoStatus>>,
+    metrics: Arc<Mutex<RepoMetrics>>,
+}
+
+impl RunContext {
+    /// Runs `jobs` with up to `max_parallel_jobs` at the same time. A job is started once all
+    /// jobs it needs finished, and skipped if one of them did not succeed.
+    fn run_jobs(
+        self: Arc<Self>,
+        jobs: Vec<(String, Job)>,
+        max_parallel_jobs: usize,
+        job_finished_tx: Sender<ToRunMsg>,
+        to_run_rx: Receiver<ToRunMsg>,
+    ) {
+        let job_names: HashSet<String> = jobs.iter().map(|(name, _)| name.clone()).collect();
+        let mut pending = jobs;
+        let mut running: HashMap<String, (JoinHandle<()>, Sender<()>, Instant)> = HashMap::new();
+        let mut finished = HashSet::new();
+        // jobs that failed or were skipped, their dependents are skipped as well
+        let mut unsuccessful_jobs = HashSet::new();
+        let mut run_outcome = RunOutcome::Succeeded;
+        let mut canceled = false;
 
-                let mut script = String::new();
-                for cmd in job.script.unwrap_or_default() {
-                    let cmd_echo = cmd.lines().map(|l| format!("+ {l}")).join("\n");
-                    script.push_str(&format!(
-                        "echo {}\n{}\n",
-                        shell_escape::escape(Cow::from(cmd_echo)),
-                        cmd
-                    ));
+        loop {
+            let mut i = 0;
+            while !canceled && i < pending.len() && running.len() < max_parallel_jobs {
+                let needs_finished = pending[i]
+                    .1
+                    .needs
+                    .iter()
+                    .all(|need| !job_names.contains(need) || finished.contains(need));
+                if !needs_finished {
+                    i += 1;
+                    continue;
                 }
 
-                let mut child = Command::new("sh")
-                    .current_dir(&repo_path)
-                    .args(["-e", "-c", &script])
-                    .env("HOST_OS", env::consts::OS)
-                    .env("HOST_ARCH", env::consts::ARCH)
-                    .stdout(Stdio::piped())
-                    .stderr(Stdio::piped())
-                    .stdin(Stdio::null())
-                    .process_group(0) // prevent child processes from receiving signals
-                    .spawn()
-                    .unwrap();
-
-                let child_stdout = child
-                    .stdout
-                    .take()
-                    .expect("Internal error, could not take stdout");
-                let child_stderr = child
-                    .stderr
-                    .take()
-                    .expect("Internal error, could not take stderr");
-
-                let (out_tx, out_rx) = std::sync::mpsc::channel();
-
-                let out_tx2 = out_tx.clone();
-                let stdout_task = thread::spawn(move || {
-                    let mut lines = BufReader::new(child_stdout).lines();
-                    while let Some(Ok(line)) = lines.next() {
-                        println!("{}", line);
-                        out_tx2.send(line).unwrap();
-                    }
-                });
+                let (job_name, job) = pending.remove(i);
+                let failed_need = job
+                    .needs
+                    .iter()
+                    .find(|need| unsuccessful_jobs.contains(*need));
+                if let Some(failed_need) = failed_need {
+                    log::event(
+                        Event::JobSkipped {
+                            repo: &self.repo_url,
+                            commit: &self.commit_id.to_string(),
+                            job: &job_name,
+                            reason: format!("{failed_need} did not succeed"),
+                        },
+                        Some(
+                            format!("Skipping job {job_name}, {failed_need} did not succeed")
+                                .bold()
+                                .dark_grey(),
+                        ),
+                    );
+                    self.set_job_status(
+                        &job_name,
+                        StatusState::Error,
+                        format!("was skipped, {failed_need} did not succeed"),
+                        None,
+                    );
+                    finished.insert(job_name.clone());
+                    unsuccessful_jobs.insert(job_name);
+                    continue;
+                }
 
-                let stderr_task = thread::spawn(move || {
-                    let mut lines = BufReader::new(child_stderr).lines();
-                    while let Some(Ok(line)) = lines.next() {
-                        println!("{}", line);
-                        out_tx.send(line).unwrap();
-                    }
+                let (cancel_tx, cancel_rx) = mpsc::channel();
+                let run = self.clone();
+                let job_finished_tx = job_finished_tx.clone();
+                let name = job_name.clone();
+                let handle = thread::spawn(move || {
+                    // the run waits for every job to finish, so a panicking job must not go
+                    // unreported
+                    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+                        run.run_job(&name, job, cancel_rx)
+                    }))
+                    .unwrap_or_else(|_err| {
+                        run.set_job_status(
+                            &name,
+                            StatusState::Failure,
+                            "failed: internal error".to_owned(),
+                            None,
+                        );
+                        JobOutcome::Failed
+                    });
+                    let _ = job_finished_tx.send(ToRunMsg::JobFinished(name, outcome));
                 });
+                self.status.lock().unwrap().running_jobs.push(job_name.clone());
+                self.metrics.lock().unwrap().job_started(&job_name);
+                running.insert(job_name, (handle, cancel_tx, Instant::now()));
+            }
 
-                while child.try_wait().is_ok_and(|res| res.is_none()) {
-                    let rec = to_run_rx.recv_timeout(Duration::from_millis(1));
+            if running.is_empty() {
+                break;
+            }
+
+            match to_run_rx.recv() {
+                Ok(ToRunMsg::JobFinished(job_name, outcome)) => {
+                    if let Some((handle, _, started)) = running.remove(&job_name) {
+                        let _ = handle.join();
+                        self.metrics
+                            .lock()
+                            .unwrap()
+                            .job_finished(&job_name, outcome, started.elapsed());
+
+                        let mut status = self.status.lock().unwrap();
+                        status.running_jobs.retain(|name| *name != job_name);
+                        status.jobs.insert(
+                            job_name.clone(),
+                            JobStatus {
+                                outcome,
+                                commit: self.commit_id.to_string(),
+                                finished_at: unix_time(),
+                                duration_secs: started.elapsed().as_secs_f64(),
+                            },
+                        );
+                    }
 
-                    match rec {
-                        Err(RecvTimeoutError::Timeout) => {}
-                        Err(err) => {
-                            println!("Failed to receive message: {}", err);
-                            child.kill().unwrap();
-                            job_failed = true;
-                            break;
+                    match outcome {
+                        JobOutcome::Succeeded => {}
+                        JobOutcome::Failed | JobOutcome::TimedOut => {
+                            if run_outcome == RunOutcome::Succeeded {
+                                run_outcome = RunOutcome::Failed;
+                            }
+                            unsuccessful_jobs.insert(job_name.clone());
                         }
-                        Ok(ToRunMsg::Cancel) => {
-                            child.kill().unwrap();
-                            job_canceled = true;
-                            break;
+                        JobOutcome::Canceled => {
+                            run_outcome = RunOutcome::Canceled;
+                            unsuccessful_jobs.insert(job_name.clone());
                         }
                     }
+                    finished.insert(job_name);
+                }
+                Ok(ToRunMsg::Cancel) => {
+                    canceled = true;
+                    for (_, cancel_tx, _) in running.values() {
+                        let _ = cancel_tx.send(());
+                    }
+                }
+                Err(err) => {
+                    self.log(
+                        Level::Error,
+                        None,
+                        format!("Failed to receive message: {}", err).stylize(),
+                    );
+                    canceled = true;
                 }
+            }
+        }
+
+        if canceled {
+            run_outcome = RunOutcome::Canceled;
+        }
 
-                let status = child
-                    .wait()
-                    .expect("Internal error, failed to wait on child command");
+        // jobs that did not get to run would stay pending forever otherwise
+        for (job_name, _) in pending {
+            self.set_job_status(&job_name, StatusState::Error, "was canceled".to_owned(), None);
+        }
+
+        let finished_at = unix_time();
+        update_state(&self.state, |state| {
+            state.last_run_outcome = Some(run_outcome);
+            if run_outcome == RunOutcome::Succeeded {
+                state.last_successful_run_at = Some(finished_at);
+            }
+        });
+        {
+            let mut status = self.status.lock().unwrap();
+            status.running_commit = None;
+            status.last_run_outcome = Some(run_outcome);
+        }
+        {
+            let mut metrics = self.metrics.lock().unwrap();
+            metrics.run_finished(run_outcome);
+            if run_outcome == RunOutcome::Succeeded {
+                metrics.last_successful_run_at = Some(finished_at);
+            }
+        }
+        log::event(
+            Event::RunFinished {
+                repo: &self.repo_url,
+                commit: &self.commit_id.to_string(),
+                outcome: run_outcome,
+            },
+            Some("Run finished".bold()),
+        );
+    }
 
-                stdout_task.join().unwrap();
-                stderr_task.join().unwrap();
+    fn run_job(&self, job_name: &str, job: Job, cancel_rx: Receiver<()>) -> JobOutcome {
+        let line_prefix = self.line_prefix(job_name);
+        let attempts = job.retry.as_ref().map_or(1, |retry| retry.attempts.max(1));
+        let backoff = job.retry.as_ref().and_then(|retry| retry.backoff).unwrap_or(1.0);
 
-                output.push_str(
-                    out_rx
-                        .into_iter()
-                        .collect::<Vec<String>>()
-                        .join("")
-                        .as_str(),
+        let JobSettings {
+            timeout,
+            retry_delay: mut delay,
+            secrets,
+        } = match self.job_settings(&job) {
+            Ok(settings) => settings,
+            Err(err) => {
+                self.log(
+                    Level::Error,
+                    Some(job_name),
+                    format!("{line_prefix}Job failed: {}", err).bold().red(),
                 );
+                self.set_job_status(job_name, StatusState::Failure, format!("failed: {}", err), None);
+                return JobOutcome::Failed;
+            }
+        };
 
-                if !status.success() {
-                    job_failed = true;
+        let commit = self.commit_id.to_string();
+        let mut attempt = 1;
+        loop {
+            log::event(
+                Event::JobStarted {
+                    repo: &self.repo_url,
+                    commit: &commit,
+                    job: job_name,
+                    attempt,
+                    attempts,
+                },
+                // retries are announced when the previous attempt failed
+                (attempt == 1).then(|| format!("Running job {job_name}...").bold()),
+            );
+            let attempt_info = if attempts > 1 {
+                format!(" (attempt {attempt}/{attempts})")
+            } else {
+                String::new()
+            };
+            self.set_job_status(
+                job_name,
+                StatusState::Running,
+                format!("is running{attempt_info}..."),
+                None,
+            );
+
+            let started = Instant::now();
+            let (outcome, output, exit_code) =
+                self.run_script(job_name, &job, &secrets, timeout, &cancel_rx);
+            let retried = matches!(outcome, JobOutcome::Failed | JobOutcome::TimedOut)
+                && attempt < attempts;
+
+            let text = match outcome {
+                _ if retried => {
+                    format!("{line_prefix}Job failed, retrying in {}s...", delay.as_secs())
+                        .bold()
+                        .dark_yellow()
                 }
+                JobOutcome::TimedOut => format!("{line_prefix}Job timed out").bold().red(),
+                JobOutcome::Canceled => format!("{line_prefix}Job canceled").bold().dark_grey(),
+                JobOutcome::Failed => format!("{line_prefix}Job failed ").bold().red(),
+                JobOutcome::Succeeded => format!("{line_prefix}Job succeeded").bold().green(),
+            };
+            log::event(
+                Event::JobFinished {
+                    repo: &self.repo_url,
+                    commit: &commit,
+                    job: job_name,
+                    attempt,
+                    attempts,
+                    outcome,
+                    duration_secs: started.elapsed().as_secs_f64(),
+                    exit_code,
+                    retry_in_secs: retried.then_some(delay.as_secs()),
+                },
+                Some(text),
+            );
 
-                if job_canceled {
-                    println!("{}", "Job canceled".bold().dark_grey());
-                    let _ = forge.set_commit_status(
-                        &commit_id.to_string(),
-                        CreateStatus {
-                            state: StatusState::Error,
-                            description: Some(format!(
-                                "Job {job_name} on host {host_identifier} was canceled"
-                            )),
-                            context: format!("pulld/{}/{}", job_name, host_identifier),
-                            target_url: None,
-                        },
+            match outcome {
+                _ if retried => {
+                    // every attempt gets its own status, GitLab rejects reporting running twice
+                    self.set_job_status(
+                        job_name,
+                        StatusState::Failure,
+                        format!("failed{attempt_info}, retrying in {}s...", delay.as_secs()),
+                        Some(output.clone()),
                     );
-                } else if job_failed {
-                    println!("{}", "Job failed ".bold().red());
-                    let _ = forge.set_commit_status(
-                        &commit_id.to_string(),
-                        CreateStatus {
-                            state: StatusState::Error,
-                            description: Some(format!(
-                                "Job {job_name} on host {host_identifier} failed"
-                            )),
-                            context: format!("pulld/{}/{}", job_name, host_identifier),
-                            target_url: None,
-                        },
+
+                    if let Ok(()) | Err(RecvTimeoutError::Disconnected) =
+                        cancel_rx.recv_timeout(delay)
+                    {
+                        self.log(
+                            Level::Warn,
+                            Some(job_name),
+                            format!("{line_prefix}Job canceled").bold().dark_grey(),
+                        );
+                        self.set_job_status(
+                            job_name,
+                            StatusState::Error,
+                            format!("was canceled{attempt_info}"),
+                            Some(output),
+                        );
+                        return JobOutcome::Canceled;
+                    }
+
+                    delay = Duration::try_from_secs_f64(delay.as_secs_f64() * backoff)
+                        .unwrap_or(MAX_RETRY_DELAY)
+                        .min(MAX_RETRY_DELAY);
+                    attempt += 1;
+                }
+                JobOutcome::TimedOut => {
+                    let timeout = timeout.unwrap_or_default().as_secs();
+                    self.set_job_status(
+                        job_name,
+                        StatusState::Failure,
+                        format!("timed out after {timeout}s{attempt_info}"),
+                        Some(output),
                     );
-                } else {
-                    println!("{}", "Job succeeded".bold().green());
-                    let _ = forge.set_commit_status(
-                        &commit_id.to_string(),
-                        CreateStatus {
-                            state: StatusState::Success,
-                            description: Some(format!(
-                                "Job {job_name} on host {host_identifier} was successful"
-                            )),
-                            context: format!("pulld/{}/{}", job_name, host_identifier),
-                            target_url: None,
-                        },
+                    return outcome;
+                }
+                JobOutcome::Canceled => {
+                    self.set_job_status(
+                        job_name,
+                        StatusState::Error,
+                        format!("was canceled{attempt_info}"),
+                        Some(output),
+                    );
+                    return outcome;
+                }
+                JobOutcome::Failed => {
+                    self.set_job_status(
+                        job_name,
+                        StatusState::Failure,
+                        format!("failed{attempt_info}"),
+                        Some(output),
+                    );
+                    return outcome;
+                }
+                JobOutcome::Succeeded => {
+                    update_state(&self.state, |state| {
+                        state
+                            .last_successful_commits
+                            .insert(job_name.to_owned(), self.commit_id.to_string());
+                    });
+                    self.set_job_status(
+                        job_name,
+                        StatusState::Success,
+                        format!("was successful{attempt_info}"),
+                        Some(output),
                     );
+                    return outcome;
                 }
             }
+        }
+    }
+
+    /// Resolves the timeout, the retry delay and the secrets of a job.
+    fn job_settings(&self, job: &Job) -> Result<JobSettings> {
+        let timeout = job.timeout.as_deref().map(parse_duration).transpose()?;
+        let delay = job
+            .retry
+            .as_ref()
+            .and_then(|retry| retry.delay.as_deref())
+            .map(parse_duration)
+            .transpose()?;
+        let secrets = self.secrets.resolve(&job.secrets)?;
+
+        Ok(JobSettings {
+            timeout: timeout.or(self.job_timeout),
+            retry_delay: delay.unwrap_or_default(),
+            secrets,
+        })
+    }
+
+    /// Logs a message about the repository or one of its jobs.
+    fn log<T: Display>(&self, level: Level, job_name: Option<&str>, text: StyledContent<T>) {
+        let scope = Scope {
+            repo: Some(&self.repo_url),
+            job: job_name,
+        };
+        log::message(level, scope, text);
+    }
+
+    fn line_prefix(&self, job_name: &str) -> String {
+        if self.prefix_output {
+            format!("[{job_name}] ")
+        } else {
+            String::new()
+        }
+    }
+
+    /// Runs the script of a job once and returns how it ended together with its output and exit
+    /// code.
+    fn run_script(
+        &self,
+        job_name: &str,
+        job: &Job,
+        secrets: &[(String, String)],
+        timeout: Option<Duration>,
+        cancel_rx: &Receiver<()>,
+    ) -> (JobOutcome, String, Option<i32>) {
+        let mut job_failed = false;
+        let mut job_canceled = false;
+
+        let secret_values = Arc::new(mask_patterns(secrets));
+
+        let mut script = String::new();
+        for cmd in job.script.iter().flatten() {
+            let cmd_echo = cmd.lines().map(|l| format!("+ {l}")).join("\n");
+            script.push_str(&format!(
+                "echo {}\n{}\n",
+                shell_escape::escape(Cow::from(cmd_echo)),
+                cmd
+            ));
+        }
+
+        let previous_sha = self
+            .state
+            .lock()
+            .unwrap()
+            .state()
+            .last_successful_commits
+            .get(job_name)
+            .cloned()
+            .unwrap_or_default();
+
+        let child = Command::new("sh")
+            .current_dir(&self.repo_path)
+            .args(["-e", "-c", &script])
+            .envs(&job.env)
+            .envs(secrets.iter().map(|(name, value)| (name, value)))
+            .env("HOST_OS", env::consts::OS)
+            .env("HOST_ARCH", env::consts::ARCH)
+            .env("PULLD_COMMIT_SHA", self.commit_id.to_string())
+            .env("PULLD_PREVIOUS_SHA", previous_sha)
+            .env("PULLD_BRANCH", &self.branch)
+            .env("PULLD_HOST", &self.host_identifier)
+            .env("PULLD_JOB", job_name)
+            .env("PULLD_REPO_PATH", &self.repo_path)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .stdin(Stdio::null())
+            .process_group(0) // prevent child processes from receiving signals
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(err) => {
+                let output = format!("Failed to start job: {}", err);
+                self.log(Level::Error, Some(job_name), output.clone().bold().red());
+                return (JobOutcome::Failed, output, None);
+            }
+        };
 
-            println!("{}", "Run finished".bold());
+        let child_stdout = child
+            .stdout
+            .take()
+            .expect("Internal error, could not take stdout");
+        let child_stderr = child
+            .stderr
+            .take()
+            .expect("Internal error, could not take stderr");
+
+        let (out_tx, out_rx) = mpsc::channel();
+        let line_prefix = self.line_prefix(job_name);
+
+        let job_output = JobOutput {
+            repo_url: self.repo_url.clone(),
+            commit: self.commit_id.to_string(),
+            job_name: job_name.to_owned(),
+            line_prefix: line_prefix.clone(),
+            secret_values,
+        };
+
+        let stdout_output = job_output.clone();
+        let out_tx2 = out_tx.clone();
+        let stdout_task = thread::spawn(move || {
+            stdout_output.forward(child_stdout, Stream::Stdout, &out_tx2);
         });
 
-        self.run_handle_and_sender = Some((run_handle, to_run_tx));
+        let stderr_task = thread::spawn(move || {
+            job_output.forward(child_stderr, Stream::Stderr, &out_tx);
+        });
 
-        Ok(())
+        let started = Instant::now();
+        let mut job_timed_out = false;
+        while child.try_wait().is_ok_and(|res| res.is_none()) {
+            if timeout.is_some_and(|timeout| started.elapsed() >= timeout) {
+                self.log(
+                    Level::Warn,
+                    Some(job_name),
+                    format!("{line_prefix}Job timed out, terminating...").bold().dark_grey(),
+                );
+                terminate_process_group(&mut child, self.termination_grace_period);
+                job_timed_out = true;
+                break;
+            }
+
+            let rec = cancel_rx.recv_timeout(Duration::from_millis(1));
+
+            match rec {
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(err) => {
+                    self.log(
+                        Level::Error,
+                        Some(job_name),
+                        format!("Failed to receive message: {}", err).stylize(),
+                    );
+                    terminate_process_group(&mut child, self.termination_grace_period);
+                    job_failed = true;
+                    break;
+                }
+                Ok(()) => {
+                    terminate_process_group(&mut child, self.termination_grace_period);
+                    job_canceled = true;
+                    break;
+                }
+            }
+        }
+
+        let status = child
+            .wait()
+            .expect("Internal error, failed to wait on child command");
+
+        stdout_task.join().unwrap();
+        stderr_task.join().unwrap();
+
+        let output = out_rx.into_iter().collect::<Vec<String>>().join("\n");
+
+        if !status.success() {
+            job_failed = true;
+        }
+
+        let outcome = if job_timed_out {
+            JobOutcome::TimedOut
+        } else if job_canceled {
+            JobOutcome::Canceled
+        } else if job_failed {
+            JobOutcome::Failed
+        } else {
+            JobOutcome::Succeeded
+        };
+        (outcome, output, status.code())
     }
+
+    /// Reports the state of a job, `description` is prefixed with the job and host name.
+    fn set_job_status(
+        &self,
+        job_name: &str,
+        state: StatusState,
+        description: String,
+        output: Option<String>,
+    ) {
+        let host_identifier = &self.host_identifier;
+        let res = self.forge.set_commit_status(
+            &self.commit_id.to_string(),
+            CreateStatus {
+                state,
+                description: Some(format!(
+                    "Job {job_name} on host {host_identifier} {description}"
+                )),
+                context: format!("pulld/{}/{}", job_name, host_identifier),
+                target_url: None,
+                output,
+            },
+        );
+        if let Err(err) = res {
+            self.log(
+                Level::Error,
+                Some(job_name),
+                format!("Failed to set status of job {job_name}: {}", err).stylize(),
+            );
+        }
+    }
+}
+
+/// Logs the output of a job and collects it for the forge.
+#[derive(Clone)]
+struct JobOutput {
+    repo_url: String,
+    commit: String,
+    job_name: String,
+    line_prefix: String,
+    secret_values: Arc<Vec<String>>,
+}
+
+impl JobOutput {
+    fn forward(&self, output: impl Read, stream: Stream, out_tx: &Sender<String>) {
+        let mut lines = BufReader::new(output).lines();
+        while let Some(Ok(line)) = lines.next() {
+            let line = mask_secrets(&line, &self.secret_values);
+            log::event(
+                Event::JobOutput {
+                    repo: &self.repo_url,
+                    commit: &self.commit,
+                    job: &self.job_name,
+                    stream,
+                    line: &line,
+                },
+                Some(format!("{}{}", self.line_prefix, line).stylize()),
+            );
+            out_tx.send(line).unwrap();
+        }
+    }
+}
+
+struct JobSettings {
+    timeout: Option<Duration>,
+    retry_delay: Duration,
+    secrets: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobOutcome {
+    Succeeded,
+    Failed,
+    TimedOut,
+    Canceled,
+}
+
+/// Sends SIGTERM to the process group of `child`, which is spawned as the leader of its own
+/// group, and SIGKILL if it is still running after `grace_period`. Returns once all processes
+/// of the group exited, so they can't interfere with the next run.
+fn terminate_process_group(child: &mut Child, grace_period: Duration) {
+    signal_process_group(child, libc::SIGTERM);
+
+    let terminated = Instant::now();
+    let mut killed = false;
+    // the group exists until the shell is reaped and all of its descendants exited
+    while child.try_wait().is_ok_and(|res| res.is_none()) || process_group_exists(child) {
+        if !killed && terminated.elapsed() >= grace_period {
+            signal_process_group(child, libc::SIGKILL);
+            killed = true;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn process_group_exists(child: &Child) -> bool {
+    signal_process_group(child, 0)
+}
+
+/// Returns whether the signal was delivered to at least one process of the group.
+fn signal_process_group(child: &Child, signal: libc::c_int) -> bool {
+    // SAFETY: killpg has no memory safety requirements, it fails if the group is gone
+    unsafe { libc::killpg(child.id() as libc::pid_t, signal) == 0 }
 }
 
 pub enum ToRunMsg {
     Cancel,
+    JobFinished(String, JobOutcome),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::Path,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+    use crate::forge::Status;
+
+    /// Forge that records the statuses set by the runner.
+    #[derive(Default)]
+    struct RecordingForge {
+        statuses: Vec<Status>,
+        /// Index of the call to `set_commit_status` that fails.
+        failing_call: Option<usize>,
+        calls: AtomicUsize,
+        set: Mutex<Vec<CreateStatus>>,
+    }
+
+    impl RecordingForge {
+        /// Last state and description set for every context.
+        fn final_states(&self) -> HashMap<String, (StatusState, String)> {
+            self.set
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|status| {
+                    let description = status.description.clone().unwrap_or_default();
+                    (status.context.clone(), (status.state, description))
+                })
+                .collect()
+        }
+
+        /// Context and state of the statuses set so far.
+        fn set_states(&self) -> Vec<(String, StatusState)> {
+            self.set
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|status| (status.context.clone(), status.state))
+                .collect()
+        }
+    }
+
+    impl Forge for RecordingForge {
+        fn get_commit_statuses(&self, _sha: &str) -> Result<Vec<Status>> {
+            Ok(self.statuses.clone())
+        }
+
+        fn set_commit_status(&self, _sha: &str, status: CreateStatus) -> Result<()> {
+            if self.failing_call == Some(self.calls.fetch_add(1, Ordering::SeqCst)) {
+                return Err(anyhow!("forge unavailable"));
+            }
+            self.set.lock().unwrap().push(status);
+            Ok(())
+        }
+
+        fn git_ssh_url(&self) -> String {
+            String::new()
+        }
+    }
+
+    /// Empty directory for a test, removed from previous runs.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("pulld-runner-test-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Clones a repository with a single commit of `workflow` as `.pulld.yaml` into `dir`.
+    fn test_repo(dir: &Path, workflow: &str) -> (GitRepo, git2::Oid) {
+        let src = dir.join("src");
+        let mut init_options = git2::RepositoryInitOptions::new();
+        init_options.initial_head("main");
+        let src_repo = git2::Repository::init_opts(&src, &init_options).unwrap();
+        std::fs::write(src.join(".pulld.yaml"), workflow).unwrap();
+        let mut index = src_repo.index().unwrap();
+        index.add_path(Path::new(".pulld.yaml")).unwrap();
+        let tree = src_repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("pulld", "pulld@example.com").unwrap();
+        let commit_id = src_repo
+            .commit(Some("HEAD"), &signature, &signature, "workflow", &tree, &[])
+            .unwrap();
+
+        let url = format!("file://{}", src.display());
+        (GitRepo::new(&dir.join("checkout"), &url, "main", None), commit_id)
+    }
+
+    fn runner(forge: Arc<RecordingForge>, dir: &Path, max_parallel_jobs: usize) -> Runner {
+        let state = StateStore::load(&dir.join("pulld-state.json")).unwrap();
+        Runner::new(
+            "file:///repo".to_owned(),
+            forge,
+            Arc::new(Mutex::new(state)),
+            RunnerConfig {
+                max_parallel_jobs,
+                host_labels: HashMap::new(),
+                secrets: SecretStore::default(),
+                job_timeout: None,
+                termination_grace_period: Duration::from_secs(1),
+            },
+            Arc::new(Mutex::new(RepoStatus::default())),
+            Arc::new(Mutex::new(RepoMetrics::default())),
+        )
+    }
+
+    fn status(context: &str, state: StatusState) -> Status {
+        Status {
+            state,
+            description: None,
+            target_url: None,
+            context: Some(context.to_owned()),
+        }
+    }
+
+    #[test]
+    fn run_skips_dependents_of_failed_jobs() {
+        let dir = test_dir("needs");
+        let (repo, commit_id) = test_repo(
+            &dir,
+            r#"
+jobs:
+  build: { hosts: [host-1], script: ["false"] }
+  deploy: { hosts: [host-1], needs: [build], script: ["true"] }
+  notify: { hosts: [host-1], needs: [deploy], script: ["true"] }
+  lint: { hosts: [host-1], needs: [other-host], script: ["true"] }
+  other-host: { hosts: [host-2], script: ["true"] }
+"#,
+        );
+        let forge = Arc::new(RecordingForge::default());
+        let mut runner = runner(forge.clone(), &dir, 1);
+
+        runner.start_run(&repo, commit_id, "host-1", false).unwrap();
+        runner.wait_for_run().unwrap();
+
+        let states = forge.final_states();
+        assert_eq!(states.len(), 4);
+        assert_eq!(states["pulld/build/host-1"].0, StatusState::Failure);
+        let deploy = &states["pulld/deploy/host-1"];
+        assert_eq!(deploy.0, StatusState::Error);
+        assert_eq!(deploy.1, "Job deploy on host host-1 was skipped, build did not succeed");
+        let notify = &states["pulld/notify/host-1"];
+        assert_eq!(notify.0, StatusState::Error);
+        assert_eq!(notify.1, "Job notify on host host-1 was skipped, deploy did not succeed");
+        assert_eq!(states["pulld/lint/host-1"].0, StatusState::Success);
+    }
+
+    #[test]
+    fn run_limits_parallel_jobs() {
+        let dir = test_dir("parallel");
+        let log = dir.join("jobs.log");
+        let script = format!(r#"["echo start >> {0}; sleep 0.2; echo end >> {0}"]"#, log.display());
+        let (repo, commit_id) = test_repo(
+            &dir,
+            &format!(
+                "jobs:\n{}",
+                ["a", "b", "c", "d", "e"]
+                    .iter()
+                    .map(|name| format!("  {name}: {{ hosts: [host-1], script: {script} }}\n"))
+                    .join("")
+            ),
+        );
+        let forge = Arc::new(RecordingForge::default());
+        let mut runner = runner(forge.clone(), &dir, 2);
+
+        runner.start_run(&repo, commit_id, "host-1", false).unwrap();
+        runner.wait_for_run().unwrap();
+
+        let mut running = 0;
+        let mut max_running = 0;
+        for line in std::fs::read_to_string(&log).unwrap().lines() {
+            running += if line == "start" { 1 } else { -1 };
+            max_running = max_running.max(running);
+        }
+        assert_eq!(max_running, 2);
+        assert!(forge.final_states().values().all(|(state, _)| *state == StatusState::Success));
+    }
+
+    #[test]
+    fn cancel_run_stops_running_jobs_and_resolves_the_others() {
+        let dir = test_dir("cancel");
+        let (repo, commit_id) = test_repo(
+            &dir,
+            r#"
+jobs:
+  deploy: { hosts: [host-1], script: ["sleep 30"] }
+  notify: { hosts: [host-1], needs: [deploy], script: ["true"] }
+"#,
+        );
+        let forge = Arc::new(RecordingForge::default());
+        let mut runner = runner(forge.clone(), &dir, 1);
+
+        runner.start_run(&repo, commit_id, "host-1", false).unwrap();
+        let deploy_running = ("pulld/deploy/host-1".to_owned(), StatusState::Running);
+        while !forge.set_states().contains(&deploy_running) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let canceled = Instant::now();
+        runner.cancel_run().unwrap();
+
+        assert!(canceled.elapsed() < Duration::from_secs(10));
+        let states = forge.final_states();
+        assert_eq!(
+            states["pulld/deploy/host-1"],
+            (StatusState::Error, "Job deploy on host host-1 was canceled".to_owned())
+        );
+        assert_eq!(
+            states["pulld/notify/host-1"],
+            (StatusState::Error, "Job notify on host host-1 was canceled".to_owned())
+        );
+        // canceling a finished run is a no-op
+        runner.cancel_run().unwrap();
+    }
+
+    #[test]
+    fn retries_report_every_attempt_with_its_own_status() {
+        let dir = test_dir("retry");
+        let (repo, commit_id) = test_repo(
+            &dir,
+            r#"
+jobs:
+  deploy: { hosts: [host-1], retry: { attempts: 2, delay: 0s }, script: ["false"] }
+"#,
+        );
+        let forge = Arc::new(RecordingForge::default());
+        let mut runner = runner(forge.clone(), &dir, 1);
+
+        runner.start_run(&repo, commit_id, "host-1", false).unwrap();
+        runner.wait_for_run().unwrap();
+
+        let states = forge.set_states().into_iter().map(|(_, state)| state).collect_vec();
+        assert_eq!(
+            states,
+            [
+                StatusState::Pending,
+                StatusState::Running,
+                StatusState::Failure,
+                StatusState::Running,
+                StatusState::Failure,
+            ]
+        );
+    }
+
+    #[test]
+    fn start_run_resolves_pending_jobs_if_it_fails() {
+        let dir = test_dir("start-failed");
+        let (repo, commit_id) = test_repo(
+            &dir,
+            r#"
+jobs:
+  a: { hosts: [host-1], script: ["true"] }
+  b: { hosts: [host-1], script: ["true"] }
+  c: { hosts: [host-1], script: ["true"] }
+"#,
+        );
+        let forge = Arc::new(RecordingForge {
+            failing_call: Some(2),
+            ..Default::default()
+        });
+        let mut runner = runner(forge.clone(), &dir, 1);
+
+        assert!(runner.start_run(&repo, commit_id, "host-1", false).is_err());
+
+        assert_eq!(
+            forge.set_states(),
+            [
+                ("pulld/a/host-1".to_owned(), StatusState::Pending),
+                ("pulld/b/host-1".to_owned(), StatusState::Pending),
+                ("pulld/a/host-1".to_owned(), StatusState::Error),
+                ("pulld/b/host-1".to_owned(), StatusState::Error),
+            ]
+        );
+        assert!(!runner.is_running());
+    }
+
+    #[test]
+    fn mark_interrupted_jobs_only_marks_pending_jobs_of_this_host() {
+        let forge = Arc::new(RecordingForge {
+            statuses: vec![
+                status("pulld/build/host-1", StatusState::Success),
+                status("pulld/deploy/host-1", StatusState::Running),
+                status("pulld/migrate/host-1", StatusState::Pending),
+                status("pulld/deploy/host-2", StatusState::Pending),
+                status("ci/test", StatusState::Pending),
+            ],
+            ..Default::default()
+        });
+        let runner = runner(forge.clone(), &test_dir("interrupted"), 1);
+
+        runner.mark_interrupted_jobs("abc", "host-1").unwrap();
+
+        assert_eq!(
+            forge.set_states(),
+            [
+                ("pulld/deploy/host-1".to_owned(), StatusState::Error),
+                ("pulld/migrate/host-1".to_owned(), StatusState::Error),
+            ]
+        );
+    }
 }
@@ -0,0 +1,169 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+
+/// Secrets stored on the local host, outside of the watched repo. They are read when a job
+/// starts, so secrets can be rotated without restarting pulld.
+#[derive(Debug, Clone, Default)]
+pub struct SecretStore {
+    /// Directory with one file per secret, named after the secret.
+    pub dir: Option<PathBuf>,
+    /// Env file with one `NAME=VALUE` per line.
+    pub env_file: Option<PathBuf>,
+}
+
+impl SecretStore {
+    /// Looks up the values of `names`, secrets in the directory take precedence over the env file.
+    pub fn resolve(&self, names: &[String]) -> Result<Vec<(String, String)>> {
+        if names.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let env_file_secrets = match &self.env_file {
+            Some(env_file) => read_env_file(&fs::read_to_string(env_file)?, env_file)?,
+            None => HashMap::new(),
+        };
+
+        names
+            .iter()
+            .map(|name| {
+                if let Some(value) = self.read_from_dir(name)? {
+                    return Ok((name.clone(), value));
+                }
+                env_file_secrets
+                    .get(name)
+                    .map(|value| (name.clone(), value.clone()))
+                    .ok_or_else(|| anyhow!("Secret {} is not available on this host", name))
+            })
+            .collect()
+    }
+
+    fn read_from_dir(&self, name: &str) -> Result<Option<String>> {
+        let Some(dir) = &self.dir else {
+            return Ok(None);
+        };
+        if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+            return Err(anyhow!("Invalid secret name {}", name));
+        }
+
+        match fs::read_to_string(dir.join(name)) {
+            Ok(value) => Ok(Some(value.trim_end_matches(['\r', '\n']).to_owned())),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Parses the content of the env file at `path`, `path` is only used for errors.
+fn read_env_file(content: &str, path: &Path) -> Result<HashMap<String, String>> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line_number, line)| {
+            let (name, value) = line.split_once('=').ok_or_else(|| {
+                anyhow!(
+                    "Invalid secrets file {} line {}, expected NAME=VALUE",
+                    path.display(),
+                    line_number
+                )
+            })?;
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(value);
+            Ok((name.trim().to_owned(), value.to_owned()))
+        })
+        .collect()
+}
+
+/// Strings to mask in job output for the values of `secrets`. Output is masked line by line, so
+/// every line of a multi-line secret like a PEM key is masked on its own as well. Lines shorter
+/// than 4 characters, like the braces of a JSON file, would mask too much output and are skipped.
+pub fn mask_patterns(secrets: &[(String, String)]) -> Vec<String> {
+    let mut patterns: Vec<String> = secrets
+        .iter()
+        .flat_map(|(_, value)| {
+            let lines = value
+                .lines()
+                .map(str::trim)
+                .filter(move |line| line.len() >= 4 && *line != value);
+            std::iter::once(value.as_str()).chain(lines)
+        })
+        .filter(|pattern| !pattern.is_empty())
+        .map(ToOwned::to_owned)
+        .collect();
+    // longer patterns first, so a secret is masked as a whole before its parts
+    patterns.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    patterns.dedup();
+    patterns
+}
+
+/// Replaces the values of secrets in a line of job output.
+pub fn mask_secrets(line: &str, secret_values: &[String]) -> String {
+    secret_values
+        .iter()
+        .filter(|value| !value.is_empty())
+        .fold(line.to_owned(), |line, value| line.replace(value.as_str(), "***"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_env_file_parses_names_and_values() {
+        let content = "# database\nDB_USER=app\n\n  DB_PASSWORD = \"pa=ss word\"  \nEMPTY=\n";
+        let secrets = read_env_file(content, Path::new("secrets.env")).unwrap();
+
+        assert_eq!(secrets.len(), 3);
+        assert_eq!(secrets["DB_USER"], "app");
+        assert_eq!(secrets["DB_PASSWORD"], "pa=ss word");
+        assert_eq!(secrets["EMPTY"], "");
+    }
+
+    #[test]
+    fn read_env_file_reports_invalid_line() {
+        let err = read_env_file("A=1\n\nnot a secret\n", Path::new("secrets.env")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid secrets file secrets.env line 3, expected NAME=VALUE"
+        );
+    }
+
+    #[test]
+    fn mask_secrets_replaces_all_values() {
+        let values = ["hunter2".to_owned(), "token".to_owned(), String::new()];
+        assert_eq!(
+            mask_secrets("login hunter2 token hunter2", &values),
+            "login *** *** ***"
+        );
+        assert_eq!(mask_secrets("nothing to hide", &values), "nothing to hide");
+    }
+
+    #[test]
+    fn mask_patterns_masks_lines_of_multi_line_secrets() {
+        let key = "-----BEGIN KEY-----\nMIIEowIBAAKCAQEA\n}\n-----END KEY-----".to_owned();
+        let secrets = [("KEY".to_owned(), key.clone()), ("PIN".to_owned(), "1234".to_owned())];
+        let patterns = mask_patterns(&secrets);
+
+        assert_eq!(
+            patterns,
+            [
+                key,
+                "-----BEGIN KEY-----".to_owned(),
+                "-----END KEY-----".to_owned(),
+                "MIIEowIBAAKCAQEA".to_owned(),
+                "1234".to_owned(),
+            ]
+        );
+        assert_eq!(mask_secrets("key: MIIEowIBAAKCAQEA }", &patterns), "key: *** }");
+    }
+}
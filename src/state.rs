@@ -0,0 +1,85 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunOutcome {
+    Running,
+    Succeeded,
+    Failed,
+    Canceled,
+}
+
+/// Deployment state that survives restarts of pulld.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct DeployState {
+    pub last_attempted_commit: Option<String>,
+    pub last_run_outcome: Option<RunOutcome>,
+    /// Unix timestamp of the last run that succeeded.
+    pub last_successful_run_at: Option<u64>,
+    /// Last commit each job ran successfully on.
+    #[serde(default)]
+    pub last_successful_commits: HashMap<String, String>,
+}
+
+impl DeployState {
+    /// Whether pulld stopped in the middle of the last run.
+    pub fn run_interrupted(&self) -> bool {
+        self.last_run_outcome == Some(RunOutcome::Running)
+    }
+}
+
+pub struct StateStore {
+    path: PathBuf,
+    state: DeployState,
+}
+
+impl StateStore {
+    /// Loads the state from `path`, starting with an empty state if the file does not exist yet.
+    pub fn load(path: &Path) -> Result<StateStore> {
+        let mut state: DeployState = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => DeployState::default(),
+            Err(err) => return Err(err.into()),
+        };
+
+        // states written before the time was recorded, the file was last written when the run
+        // finished
+        if state.last_run_outcome == Some(RunOutcome::Succeeded)
+            && state.last_successful_run_at.is_none()
+        {
+            state.last_successful_run_at = fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|time| time.as_secs());
+        }
+
+        Ok(StateStore {
+            path: path.to_path_buf(),
+            state,
+        })
+    }
+
+    pub fn state(&self) -> &DeployState {
+        &self.state
+    }
+
+    /// Applies `change` and writes the state to disk. The file is replaced atomically so a
+    /// crash never leaves a partially written state behind.
+    pub fn update(&mut self, change: impl FnOnce(&mut DeployState)) -> Result<()> {
+        change(&mut self.state);
+
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec_pretty(&self.state)?)?;
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
@@ -1,7 +1,14 @@
-use std::{collections::HashMap, fs::File, io::BufReader, path::Path};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use anyhow::{Result, anyhow};
 use itertools::Itertools;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
@@ -11,13 +18,52 @@ pub struct JobTemplate {
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 pub struct Job {
+    /// Host names, glob patterns (`web-*`) or regexes (`/^web-\d+$/`) of the hosts the job
+    /// runs on. Entries starting with `!` exclude hosts.
+    #[serde(default)]
     pub hosts: Vec<String>,
+    /// Label selectors (`role=db`, `gpu`) that all have to match for the job to run on a host.
+    /// Selectors starting with `!` exclude hosts with that label.
+    #[serde(default)]
+    pub labels: Vec<String>,
     pub script: Option<Vec<String>>,
     pub extends: Option<String>,
+    /// Glob patterns of files the job watches, it only runs if one of them changed.
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// Glob patterns of files whose changes don't trigger the job.
+    #[serde(default)]
+    pub paths_ignore: Vec<String>,
+    /// Environment variables of the job, merged over the workflow's `env`.
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Time after which the job is terminated, e.g. `90s`, `30m` or `2h`.
+    pub timeout: Option<String>,
+    /// Retries the job if it fails or times out.
+    pub retry: Option<Retry>,
+    /// Names of host-local secrets that are passed to the job as environment variables.
+    #[serde(default)]
+    pub secrets: Vec<String>,
+    /// Jobs that have to succeed before this job runs.
+    #[serde(default)]
+    pub needs: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct Retry {
+    /// Number of attempts, including the first one.
+    pub attempts: u32,
+    /// Time to wait before the next attempt, e.g. `30s`.
+    pub delay: Option<String>,
+    /// Factor the delay is multiplied with after each attempt.
+    pub backoff: Option<f64>,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 pub struct WorkflowConfig {
+    /// Environment variables of all jobs.
+    #[serde(default)]
+    pub env: HashMap<String, String>,
     pub jobs: HashMap<String, Job>,
     pub job_templates: Option<HashMap<String, JobTemplate>>,
 }
@@ -30,24 +76,328 @@ pub fn read_config(folder: &Path) -> Result<WorkflowConfig> {
     Ok(config)
 }
 
-pub fn get_jobs_for_host(cfg: &WorkflowConfig, host_id: &str) -> Result<HashMap<String, Job>> {
-    cfg.jobs
+/// Returns the jobs of a host in the order they have to run, prerequisites first.
+pub fn get_jobs_for_host(
+    cfg: &WorkflowConfig,
+    host_id: &str,
+    host_labels: &HashMap<String, String>,
+) -> Result<Vec<(String, Job)>> {
+    let all_jobs: Vec<(String, Job)> = cfg
+        .jobs
         .iter()
         .map(|(name, job)| {
+            let mut job = job.clone();
+            job.env = cfg.env.clone().into_iter().chain(job.env).collect();
+
             if let Some(extends) = job.extends.as_ref() {
                 let template = cfg.job_templates.as_ref().and_then(|templates| templates.get(extends));
                 match template {
                     None => Err(anyhow!("Template {} not found for job {}", extends, name)),
                     Some(template) => {
-                        let mut job = job.clone();
                         job.script = job.script.or(template.script.clone());
                         Ok((name.clone(), job))
                     }
                 }
             } else {
-                Ok((name.clone(), job.clone()))
+                Ok((name.clone(), job))
             }
         })
-        .filter_ok(|(_, job)| job.hosts.contains(&host_id.to_owned()))
-        .collect()
+        .collect::<Result<_>>()?;
+
+    let mut jobs = HashMap::new();
+    for (name, job) in all_jobs {
+        if let Some(backoff) = job.retry.as_ref().and_then(|retry| retry.backoff)
+            && !(backoff.is_finite() && backoff >= 0.0)
+        {
+            return Err(anyhow!("Invalid retry backoff {} in job {}", backoff, name));
+        }
+        if job_runs_on_host(&name, &job, host_id, host_labels)? {
+            jobs.insert(name, job);
+        }
+    }
+
+    for (name, job) in &jobs {
+        if let Some(missing) = job.needs.iter().find(|need| !cfg.jobs.contains_key(*need)) {
+            return Err(anyhow!("Job {} needs unknown job {}", name, missing));
+        }
+    }
+
+    sort_by_needs(jobs)
+}
+
+/// A job runs on a host that is selected by `hosts` or the positive `labels` selectors and not
+/// excluded by either.
+fn job_runs_on_host(
+    name: &str,
+    job: &Job,
+    host_id: &str,
+    host_labels: &HashMap<String, String>,
+) -> Result<bool> {
+    let mut selected_by_hosts = false;
+    for pattern in &job.hosts {
+        let (excluded, pattern) = match pattern.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern.as_str()),
+        };
+        if host_matches(pattern, host_id)
+            .map_err(|err| anyhow!("Invalid host pattern {} in job {}: {}", pattern, name, err))?
+        {
+            if excluded {
+                return Ok(false);
+            }
+            selected_by_hosts = true;
+        }
+    }
+
+    // only positive selectors select hosts, exclusions narrow the selection
+    let mut selected_by_labels = job.labels.iter().any(|selector| !selector.starts_with('!'));
+    for selector in &job.labels {
+        let (excluded, selector) = match selector.strip_prefix('!') {
+            Some(selector) => (true, selector),
+            None => (false, selector.as_str()),
+        };
+        let matches = match selector.split_once('=') {
+            Some((key, value)) => host_labels.get(key).is_some_and(|label| label == value),
+            None => host_labels.contains_key(selector),
+        };
+        if excluded && matches {
+            return Ok(false);
+        }
+        if !excluded && !matches {
+            selected_by_labels = false;
+        }
+    }
+
+    Ok(selected_by_hosts || selected_by_labels)
+}
+
+/// Matches a host name, a glob pattern or a regex enclosed in slashes against `host_id`.
+fn host_matches(pattern: &str, host_id: &str) -> Result<bool> {
+    if let Some(regex) = pattern
+        .strip_prefix('/')
+        .and_then(|pattern| pattern.strip_suffix('/'))
+    {
+        Ok(Regex::new(regex)?.is_match(host_id))
+    } else if pattern.contains(['*', '?', '[']) {
+        Ok(glob::Pattern::new(pattern)?.matches(host_id))
+    } else {
+        Ok(pattern == host_id)
+    }
+}
+
+/// Whether `changed_files` contain a file that is watched by `paths` and not ignored by
+/// `paths_ignore`. Jobs without `paths` watch all files.
+pub fn paths_changed(job: &Job, changed_files: &[PathBuf]) -> Result<bool> {
+    let compile = |patterns: &[String]| -> Result<Vec<glob::Pattern>> {
+        patterns
+            .iter()
+            .map(|pattern| {
+                glob::Pattern::new(pattern)
+                    .map_err(|err| anyhow!("Invalid path pattern {}: {}", pattern, err))
+            })
+            .collect()
+    };
+    let paths = compile(&job.paths)?;
+    let paths_ignore = compile(&job.paths_ignore)?;
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+
+    Ok(changed_files.iter().any(|file| {
+        let watched = paths.is_empty()
+            || paths
+                .iter()
+                .any(|pattern| pattern.matches_path_with(file, options));
+        watched
+            && !paths_ignore
+                .iter()
+                .any(|pattern| pattern.matches_path_with(file, options))
+    }))
+}
+
+/// Parses a duration given in seconds, optionally with a unit, e.g. `90`, `90s`, `30m` or `2h`.
+pub fn parse_duration(duration: &str) -> Result<Duration> {
+    let duration = duration.trim();
+    let (value, unit_secs) = match duration.char_indices().last() {
+        Some((i, 's')) => (&duration[..i], 1),
+        Some((i, 'm')) => (&duration[..i], 60),
+        Some((i, 'h')) => (&duration[..i], 60 * 60),
+        _ => (duration, 1),
+    };
+    let value: u64 = value
+        .trim()
+        .parse()
+        .map_err(|_err| anyhow!("Invalid duration {}, expected e.g. 90s, 30m or 2h", duration))?;
+
+    let secs = value
+        .checked_mul(unit_secs)
+        .ok_or_else(|| anyhow!("Invalid duration {}, it is too long", duration))?;
+
+    Ok(Duration::from_secs(secs))
+}
+
+/// Sorts jobs topologically by their `needs`, ordering independent jobs by name. Needed jobs
+/// that don't run on this host are ignored.
+fn sort_by_needs(mut jobs: HashMap<String, Job>) -> Result<Vec<(String, Job)>> {
+    let mut sorted = Vec::with_capacity(jobs.len());
+
+    while !jobs.is_empty() {
+        let ready = jobs
+            .iter()
+            .filter(|(_, job)| job.needs.iter().all(|need| !jobs.contains_key(need)))
+            .map(|(name, _)| name.clone())
+            .sorted()
+            .collect_vec();
+
+        if ready.is_empty() {
+            return Err(anyhow!(
+                "Jobs {} have cyclic needs",
+                jobs.keys().sorted().join(", ")
+            ));
+        }
+
+        for name in ready {
+            let job = jobs.remove(&name).unwrap();
+            sorted.push((name, job));
+        }
+    }
+
+    Ok(sorted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(hosts: &[&str], labels: &[&str]) -> Job {
+        Job {
+            hosts: hosts.iter().map(|host| host.to_string()).collect(),
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+            script: None,
+            extends: None,
+            paths: Vec::new(),
+            paths_ignore: Vec::new(),
+            env: HashMap::new(),
+            timeout: None,
+            retry: None,
+            secrets: Vec::new(),
+            needs: Vec::new(),
+        }
+    }
+
+    fn labels(labels: &[(&str, &str)]) -> HashMap<String, String> {
+        labels
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn positive_labels_select_hosts() {
+        let job = job(&[], &["role=db", "ssd"]);
+
+        let db = labels(&[("role", "db"), ("ssd", "")]);
+        assert!(job_runs_on_host("job", &job, "db-1", &db).unwrap());
+        let web = labels(&[("role", "web"), ("ssd", "")]);
+        assert!(!job_runs_on_host("job", &job, "web-1", &web).unwrap());
+        let hdd = labels(&[("role", "db")]);
+        assert!(!job_runs_on_host("job", &job, "db-2", &hdd).unwrap());
+    }
+
+    #[test]
+    fn negative_labels_only_narrow_hosts() {
+        let job = job(&["web-*"], &["!canary"]);
+
+        assert!(job_runs_on_host("job", &job, "web-1", &labels(&[])).unwrap());
+        let canary = labels(&[("canary", "")]);
+        assert!(!job_runs_on_host("job", &job, "web-2", &canary).unwrap());
+        assert!(!job_runs_on_host("job", &job, "db-1", &labels(&[])).unwrap());
+    }
+
+    #[test]
+    fn mixed_labels_select_and_exclude() {
+        let job = job(&[], &["role=web", "!canary"]);
+
+        let web = labels(&[("role", "web")]);
+        assert!(job_runs_on_host("job", &job, "web-1", &web).unwrap());
+        let canary = labels(&[("role", "web"), ("canary", "")]);
+        assert!(!job_runs_on_host("job", &job, "web-2", &canary).unwrap());
+        let db = labels(&[("role", "db")]);
+        assert!(!job_runs_on_host("job", &job, "db-1", &db).unwrap());
+    }
+
+    #[test]
+    fn sort_by_needs_orders_needed_jobs_first() {
+        let mut migrate = job(&[], &[]);
+        migrate.needs = vec!["build".to_owned()];
+        let mut deploy = job(&[], &[]);
+        deploy.needs = vec!["migrate".to_owned(), "other-host".to_owned()];
+        let jobs = HashMap::from([
+            ("deploy".to_owned(), deploy),
+            ("migrate".to_owned(), migrate),
+            ("build".to_owned(), job(&[], &[])),
+            ("assets".to_owned(), job(&[], &[])),
+        ]);
+
+        let names = sort_by_needs(jobs)
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect_vec();
+        assert_eq!(names, ["assets", "build", "migrate", "deploy"]);
+    }
+
+    #[test]
+    fn sort_by_needs_rejects_cycles() {
+        let mut a = job(&[], &[]);
+        a.needs = vec!["b".to_owned()];
+        let mut b = job(&[], &[]);
+        b.needs = vec!["a".to_owned()];
+        let jobs = HashMap::from([
+            ("a".to_owned(), a),
+            ("b".to_owned(), b),
+            ("c".to_owned(), job(&[], &[])),
+        ]);
+
+        let err = sort_by_needs(jobs).unwrap_err();
+        assert_eq!(err.to_string(), "Jobs a, b have cyclic needs");
+    }
+
+    #[test]
+    fn paths_changed_applies_paths_and_paths_ignore() {
+        let mut job = job(&[], &[]);
+        let files = |files: &[&str]| files.iter().map(PathBuf::from).collect_vec();
+
+        assert!(paths_changed(&job, &files(&["README.md"])).unwrap());
+
+        job.paths = vec!["app/**".to_owned()];
+        job.paths_ignore = vec!["app/**/*.md".to_owned()];
+        assert!(paths_changed(&job, &files(&["README.md", "app/src/main.rs"])).unwrap());
+        assert!(!paths_changed(&job, &files(&["README.md", "app/docs/guide.md"])).unwrap());
+        assert!(!paths_changed(&job, &files(&[])).unwrap());
+
+        job.paths = vec!["*.rs".to_owned()];
+        assert!(!paths_changed(&job, &files(&["app/src/main.rs"])).unwrap());
+
+        job.paths = vec!["[".to_owned()];
+        assert!(paths_changed(&job, &files(&["README.md"])).is_err());
+    }
+
+    #[test]
+    fn parse_duration_accepts_units() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration(" 30m ").unwrap(), Duration::from_secs(30 * 60));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(2 * 60 * 60));
+    }
+
+    #[test]
+    fn parse_duration_rejects_invalid_and_overflowing_durations() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("ten").is_err());
+        assert!(parse_duration("-5s").is_err());
+        assert!(parse_duration("5d").is_err());
+        assert!(parse_duration(&format!("{}h", u64::MAX)).is_err());
+    }
 }